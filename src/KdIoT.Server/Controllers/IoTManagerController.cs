@@ -162,6 +162,6 @@ namespace KdIoT.Server.Controllers {
         public void SendGlobalSwitch([FromQuery] BrokerAccessService.SwitchStates state = BrokerAccessService.SwitchStates.Switch) {
             _brokerAccessService.SendGlobalSwitch(state);
         }
-        public record struct TelemetryDto(float Temperature, float Humidity, float Pressure, DateTime SubmitedTime, DateTime MeasuredTime);
+        public record struct TelemetryDto(float? Temperature, float? Humidity, float? Pressure, DateTime SubmitedTime, DateTime MeasuredTime);
     }
 }
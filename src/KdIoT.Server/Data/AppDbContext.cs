@@ -30,9 +30,9 @@ namespace KdIoT.Server.Data {
         public Int64 TelemetryId {get; set;}
         public Device Device {get; set;} = null!;
 
-        public float Temperature {get; set;}
-        public float Humidity {get; set;}
-        public float Pressure {get; set;}
+        public float? Temperature {get; set;}
+        public float? Humidity {get; set;}
+        public float? Pressure {get; set;}
 
         public Instant SubmitedTime {get; set;}
         public Instant MeasuredTime {get; set;}
@@ -49,19 +49,19 @@ namespace KdIoT.Server.Migrations
                     b.Property<Guid>("DeviceId")
                         .HasColumnType("uuid");
 
-                    b.Property<float>("Humidity")
+                    b.Property<float?>("Humidity")
                         .HasColumnType("real");
 
                     b.Property<Instant>("MeasuredTime")
                         .HasColumnType("timestamp with time zone");
 
-                    b.Property<float>("Pressure")
+                    b.Property<float?>("Pressure")
                         .HasColumnType("real");
 
                     b.Property<Instant>("SubmitedTime")
                         .HasColumnType("timestamp with time zone");
 
-                    b.Property<float>("Temperature")
+                    b.Property<float?>("Temperature")
                         .HasColumnType("real");
 
                     b.HasKey("TelemetryId");
@@ -22,6 +22,7 @@ namespace KdIoT.Server.Services {
         CancellationTokenSource? _taskstoppingTokenSource;
         AsyncEventingBasicConsumer? _consumerTelemetry;
         AsyncEventingBasicConsumer? _consumerActivity;
+        AsyncEventingBasicConsumer? _consumerHealth;
 
         public BrokerAccessService(ILogger<BrokerAccessService> logger, SystemStatusService systemStatusService, IServiceProvider provider) {
             _logger = logger;
@@ -58,6 +59,12 @@ namespace KdIoT.Server.Services {
                      autoDelete: false,
                      arguments: null);
 
+            _channel.QueueDeclare(queue: "ServerQueueHealth",
+                     durable: false,
+                     exclusive: false,
+                     autoDelete: false,
+                     arguments: null);
+
             _consumerTelemetry = new AsyncEventingBasicConsumer(_channel);
             _consumerTelemetry.Received += TelemetryMessageRecived;
             _channel.QueueBind("ServerQueueTelemetry", "amq.topic", "iotserver.*.sendtelemetry");
@@ -72,6 +79,13 @@ namespace KdIoT.Server.Services {
                                      autoAck: true,
                                      consumer: _consumerActivity);
 
+            _consumerHealth = new AsyncEventingBasicConsumer(_channel);
+            _consumerHealth.Received += HealthMessageRecived;
+            _channel.QueueBind("ServerQueueHealth", "amq.topic", "iotserver.*.sendhealth");
+            _channel.BasicConsume(queue: "ServerQueueHealth",
+                                     autoAck: true,
+                                     consumer: _consumerHealth);
+
             var task = Task.Run(async () => await DoWork(_taskstoppingTokenSource.Token).ConfigureAwait(false)).ConfigureAwait(false);
         }
 
@@ -104,9 +118,10 @@ namespace KdIoT.Server.Services {
 
             Telemetry Telemetry = new Telemetry {
                 Device = device,
-                Humidity = message.Humidity,
-                Temperature = message.Temperature,
-                Pressure = message.Pressure,
+                //a device without e.g. a pressure sensor leaves that summary value out
+                Humidity = message.HasHumidity ? message.Humidity : null,
+                Temperature = message.HasTemperature ? message.Temperature : null,
+                Pressure = message.HasPressure ? message.Pressure : null,
                 MeasuredTime = message.Timestamp.ToDateTime().ToInstant(),
                 SubmitedTime = SystemClock.Instance.GetCurrentInstant(),
             };
@@ -134,6 +149,29 @@ namespace KdIoT.Server.Services {
             _systemStatusService.UpdateLastSeen(message.IdDevice.ToLower(), DateTime.Now);
         }
 
+        private async Task HealthMessageRecived(object model, BasicDeliverEventArgs ea) {
+            var body = ea.Body.ToArray();
+            var message = ProtoBrokerMsgs.HealthMessage.Parser.ParseFrom(body);
+            var idDeviceFromRoutingKey = ea.RoutingKey.Split('.')[1];
+            if (!StringComparer.CurrentCultureIgnoreCase.Equals(idDeviceFromRoutingKey, message.IdDevice)) {
+                _logger.LogInformation(
+                    $"idDevice from RoutingKey and from Message are incorrect, idDeviceFromRoutingKey: {idDeviceFromRoutingKey} | message.IdDevice: {message.IdDevice}");
+                return;
+            }
+
+            var sensors = string.Join(", ", message.Sensors.Select(s => $"{s.SensorId} {s.ReadSuccesses}/{s.ReadFailures}"));
+            var socTemperature = message.HasSocTemperature ? $"{message.SocTemperature:F1} C" : "n/a";
+            var display = message.Display != null
+                ? $", display refreshes full/partial/skipped/failed {message.Display.Full}/{message.Display.Partial}/{message.Display.Skipped}/{message.Display.Failed}"
+                : "";
+            _logger.LogInformation(
+                $"Health {message.IdDevice}: uptime {message.UptimeSecs}s, sensors ok/failed [{sensors}], reconnects {message.MqttReconnects}, queued {message.QueuedMessages}, SoC {socTemperature}, load {message.LoadAverage1M:F2}, available memory {message.MemoryAvailableBytes / 1024} KiB{display}");
+
+            await Task.Yield(); //just to surpass some warring
+
+            _systemStatusService.UpdateLastSeen(message.IdDevice.ToLower(), DateTime.Now);
+        }
+
         public void SendSwitch(string id_device, SwitchStates state) {
             var typestate = state switch {
                 SwitchStates.Switch => ProtoBrokerMsgs.ServerMessage.Types.Cmd.Switch,
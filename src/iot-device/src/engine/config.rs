@@ -0,0 +1,1039 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use mqtt_tls::TlsSettings;
+use serde::Deserialize;
+
+use super::{sensors::ds18b20, ProgramArgs};
+use crate::error::{DeviceError, DeviceResult};
+
+/// Device configuration loaded from `--config device.toml`.
+///
+/// Every section is optional, missing values fall back to the wiring of the original board.
+/// Command line flags are applied on top of the file with `apply_args`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub device: DeviceSection,
+    pub broker: BrokerConfig,
+    pub topics: TopicsConfig,
+    pub intervals: IntervalsConfig,
+    pub sensors: SensorsConfig,
+    pub display: DisplayConfig,
+    pub recorder: RecorderConfig,
+    pub queue: QueueConfig,
+    pub health: HealthConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub shutdown: ShutdownConfig,
+    pub timeouts: TimeoutsConfig,
+    pub simulation: SimulationConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceSection {
+    pub id: String,
+}
+
+impl Default for DeviceSection {
+    fn default() -> Self {
+        DeviceSection { id: "air".into() }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrokerConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub keep_alive_secs: u64,
+    pub tls: TlsConfig,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        BrokerConfig {
+            host: "localhost".into(),
+            port: 1883,
+            username: "theserver".into(),
+            password: "myserverpass".into(),
+            keep_alive_secs: 5,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub ca_file: Option<PathBuf>,
+    pub client_cert_file: Option<PathBuf>,
+    pub client_key_file: Option<PathBuf>,
+    pub server_name: Option<String>,
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    /// Settings for the net connector, None when TLS is disabled.
+    pub fn settings(&self) -> Option<TlsSettings> {
+        if !self.enabled {
+            return None;
+        }
+        Some(TlsSettings {
+            ca_file: self.ca_file.clone(),
+            client_cert_file: self.client_cert_file.clone(),
+            client_key_file: self.client_key_file.clone(),
+            server_name: self.server_name.clone(),
+            insecure_skip_verify: self.insecure_skip_verify,
+        })
+    }
+}
+
+/// MQTT topics, `{id}` is replaced with the device id.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopicsConfig {
+    pub telemetry: String,
+    pub receive: String,
+    pub global: String,
+    pub health: String,
+    pub activity: String,
+    pub presence: String,
+}
+
+impl Default for TopicsConfig {
+    fn default() -> Self {
+        TopicsConfig {
+            telemetry: "iotserver/{id}/sendtelemetry".into(),
+            receive: "iot/{id}/receive".into(),
+            global: "iot/global".into(),
+            health: "iotserver/{id}/sendhealth".into(),
+            activity: "iotserver/{id}/sendactivity".into(),
+            presence: "iotserver/{id}/presence".into(),
+        }
+    }
+}
+
+impl TopicsConfig {
+    pub fn resolve(&self, id_device: &str) -> TopicsConfig {
+        TopicsConfig {
+            telemetry: self.telemetry.replace("{id}", id_device),
+            receive: self.receive.replace("{id}", id_device),
+            global: self.global.replace("{id}", id_device),
+            health: self.health.replace("{id}", id_device),
+            activity: self.activity.replace("{id}", id_device),
+            presence: self.presence.replace("{id}", id_device),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntervalsConfig {
+    pub print_secs: u64,
+    pub display_secs: u64,
+    pub send_secs: u64,
+    //a reading older than this is shown and sent as unavailable
+    pub stale_secs: u64,
+}
+
+impl Default for IntervalsConfig {
+    fn default() -> Self {
+        IntervalsConfig {
+            print_secs: 8,
+            display_secs: 16,
+            send_secs: 16,
+            stale_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorsConfig {
+    //take the bus and address of the enabled I2C sensors from the chips found on i2c_buses
+    pub autodetect: bool,
+    pub i2c_buses: Vec<u8>,
+    pub aht20: Aht20Config,
+    pub bmp280: Bmp280Config,
+    pub dht22: Dht22Config,
+    pub ds18b20: Ds18b20Config,
+    //sysfs directory holding the iio:deviceN entries
+    pub iio_root: PathBuf,
+    pub iio: Vec<IioSensorConfig>,
+}
+
+impl Default for SensorsConfig {
+    fn default() -> Self {
+        SensorsConfig {
+            autodetect: false,
+            i2c_buses: vec![1],
+            aht20: Aht20Config::default(),
+            bmp280: Bmp280Config::default(),
+            dht22: Dht22Config::default(),
+            ds18b20: Ds18b20Config::default(),
+            iio_root: PathBuf::from("/sys/bus/iio/devices"),
+            iio: Vec::new(),
+        }
+    }
+}
+
+impl SensorsConfig {
+    /// Sampling interval of the sensor with the given id.
+    pub fn interval(&self, sensor_id: &str) -> Duration {
+        let secs = match sensor_id {
+            "aht20" => self.aht20.interval_secs,
+            "bmp280" => self.bmp280.interval_secs,
+            "dht22" => self.dht22.interval_secs,
+            id if ds18b20::is_probe(id) => self.ds18b20.interval_secs,
+            _ => self
+                .iio
+                .iter()
+                .find(|it| it.id == sensor_id)
+                .map(|it| it.interval_secs)
+                .unwrap_or(5),
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Aht20Config {
+    pub enabled: bool,
+    pub bus: u8,
+    pub address: u8,
+    pub interval_secs: u64,
+}
+
+impl Default for Aht20Config {
+    fn default() -> Self {
+        Aht20Config {
+            enabled: true,
+            bus: 1,
+            address: 0x38,
+            interval_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bmp280Config {
+    pub enabled: bool,
+    pub bus: u8,
+    pub address: u16,
+    pub interval_secs: u64,
+}
+
+impl Default for Bmp280Config {
+    fn default() -> Self {
+        Bmp280Config {
+            enabled: true,
+            bus: 1,
+            address: 0x77,
+            interval_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dht22Config {
+    pub enabled: bool,
+    pub temp_path: String,
+    pub humidity_path: String,
+    pub interval_secs: u64,
+}
+
+impl Default for Dht22Config {
+    fn default() -> Self {
+        Dht22Config {
+            enabled: true,
+            temp_path: "/sys/bus/iio/devices/iio:device0/in_temp_input".into(),
+            humidity_path: "/sys/bus/iio/devices/iio:device0/in_humidityrelative_input".into(),
+            interval_secs: 5,
+        }
+    }
+}
+
+/// DS18B20 probes on the w1 bus, every probe found under `w1_root` is used.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ds18b20Config {
+    pub enabled: bool,
+    pub w1_root: PathBuf,
+    //a conversion takes 750 ms per probe
+    pub interval_secs: u64,
+}
+
+impl Default for Ds18b20Config {
+    fn default() -> Self {
+        Ds18b20Config {
+            enabled: true,
+            w1_root: PathBuf::from("/sys/bus/w1/devices"),
+            interval_secs: 10,
+        }
+    }
+}
+
+/// Sensor read through the generic IIO backend.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IioSensorConfig {
+    pub id: String,
+    //name from the device's `name` file (e.g. "bme280") or the directory name (e.g. "iio:device0")
+    pub device: String,
+    pub interval_secs: u64,
+    pub channels: IioChannelsConfig,
+}
+
+impl Default for IioSensorConfig {
+    fn default() -> Self {
+        IioSensorConfig {
+            id: String::new(),
+            device: String::new(),
+            interval_secs: 5,
+            channels: IioChannelsConfig::default(),
+        }
+    }
+}
+
+/// IIO channel bound to each measurement, e.g. `temperature = "temp"` reads `in_temp_input`.
+///
+/// When nothing is bound every known channel found on the device is used.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IioChannelsConfig {
+    pub temperature: Option<String>,
+    pub humidity: Option<String>,
+    pub pressure: Option<String>,
+}
+
+/// Display controller driving the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayDriver {
+    //2.13" 250x122 e-paper
+    Ssd1680,
+    Sh1106,
+    Ssd1306,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayInterface {
+    Spi,
+    I2c,
+}
+
+/// A screen of the display, the pages rotate in the configured order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayPage {
+    //averages of the air sensors, the probes and the demo switch
+    Values,
+    //latest reading of every sensor
+    Sensors,
+    //24 h temperature and humidity sparklines
+    History,
+    //lowest and highest averages since midnight UTC
+    MinMax,
+    //device id, IP address, broker connection and last delivered telemetry
+    Status,
+}
+
+/// When a refresh is worth it, the e-paper flickers and wears with every full refresh.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RefreshConfig {
+    //a refresh is skipped while every value on the screen moved less than its deadband, 0 refreshes on any change
+    pub temperature_deadband: f32,
+    pub humidity_deadband: f32,
+    pub pressure_deadband: f32,
+    //partial refreshes in a row before a full one clears the ghosting, 0 refreshes fully every time
+    pub max_partial_refreshes: u32,
+    //a full refresh is forced once this long passed since the last one, even when every refresh in
+    //between was skipped or partial, 0 disables it
+    pub max_full_interval_secs: u64,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        RefreshConfig {
+            temperature_deadband: 0.1,
+            humidity_deadband: 0.5,
+            pressure_deadband: 0.05,
+            max_partial_refreshes: 10,
+            max_full_interval_secs: 3600,
+        }
+    }
+}
+
+/// Display wiring, pins are BCM GPIO numbers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub enabled: bool,
+    pub driver: DisplayDriver,
+    //the OLEDs also work over I2C, the e-paper only over SPI
+    pub interface: DisplayInterface,
+    //panel resolution of the OLEDs, "128x64", the e-paper is always 250x122
+    pub size: String,
+    //degrees clockwise, 270 for the e-paper and 0 for the OLEDs when not set
+    pub rotation: Option<u16>,
+    pub i2c_bus: u8,
+    pub i2c_address: u8,
+    pub spi_bus: u8,
+    pub spi_clock_hz: u32,
+    pub cs_pin: u8,
+    pub busy_pin: u8,
+    pub dc_pin: u8,
+    pub rst_pin: u8,
+    //draw a "device stopped" screen on shutdown instead of leaving the last values
+    pub stopped_screen: bool,
+    pub pages: Vec<DisplayPage>,
+    //time each page stays on the display when there is more than one
+    pub page_secs: u64,
+    pub refresh: RefreshConfig,
+    //draw into memory instead of the panel, no SPI, I2C or GPIO is touched
+    pub headless: bool,
+    //.png or .pbm, written on every headless refresh and on SIGUSR1 with any backend
+    pub snapshot_path: Option<PathBuf>,
+}
+
+impl DisplayConfig {
+    /// Resolution of the OLED panel, None when `size` is not one the driver supports.
+    pub fn oled_size(&self) -> Option<(u32, u32)> {
+        let supported: &[(u32, u32)] = match self.driver {
+            DisplayDriver::Ssd1680 => &[],
+            DisplayDriver::Sh1106 => &[(128, 64), (128, 32), (132, 64)],
+            DisplayDriver::Ssd1306 => &[(128, 64), (128, 32), (96, 16), (72, 40), (64, 48)],
+        };
+        let (width, height) = self.size.split_once('x')?;
+        let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+        supported.contains(&size).then_some(size)
+    }
+
+    /// Rotation in degrees with the driver default applied.
+    pub fn rotation(&self) -> u16 {
+        match (self.rotation, self.driver) {
+            (Some(rotation), _) => rotation,
+            (None, DisplayDriver::Ssd1680) => 270,
+            (None, _) => 0,
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            enabled: true,
+            driver: DisplayDriver::Ssd1680,
+            interface: DisplayInterface::Spi,
+            size: "128x64".into(),
+            rotation: None,
+            i2c_bus: 1,
+            i2c_address: 0x3c,
+            spi_bus: 0,
+            spi_clock_hz: 8_000_000,
+            cs_pin: 26,
+            busy_pin: 21,
+            dc_pin: 16,
+            rst_pin: 20,
+            stopped_screen: true,
+            pages: vec![DisplayPage::Values],
+            page_secs: 30,
+            refresh: RefreshConfig::default(),
+            headless: false,
+            snapshot_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecorderConfig {
+    pub path: Option<PathBuf>,
+    pub max_bytes: u64,
+    //a file is also rotated once it is open this long, e.g. 86400 for a file a day, 0 disables it
+    pub max_age_secs: u64,
+    pub keep_files: u32,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        RecorderConfig {
+            path: None,
+            max_bytes: 10 * 1024 * 1024,
+            max_age_secs: 0,
+            keep_files: 5,
+        }
+    }
+}
+
+/// Store-and-forward queue for telemetry while the broker is unreachable.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub enabled: bool,
+    //holds a directory per device id, devices sharing it never publish each other's backlog
+    pub path: PathBuf,
+    //oldest messages are dropped above this count
+    pub max_messages: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            enabled: true,
+            path: state_dir().join("queue"),
+            max_messages: 20_000,
+        }
+    }
+}
+
+impl QueueConfig {
+    /// Queue directory of the device, `<path>/<id_device>`.
+    pub fn dir(&self, id_device: &str) -> PathBuf {
+        self.path.join(id_device)
+    }
+}
+
+//systemd sets STATE_DIRECTORY for a unit with StateDirectory=, a service runs in / so a relative path would not do
+fn state_dir() -> PathBuf {
+    std::env::var("STATE_DIRECTORY")
+        .ok()
+        .and_then(|it| it.split(':').next().filter(|it| !it.is_empty()).map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("/var/lib/iot-device"))
+}
+
+//state of runs by a normal user, e.g. --simulate on a laptop: $XDG_STATE_HOME, ~/.local/state,
+//then the temp dir
+fn user_state_dir() -> PathBuf {
+    let absolute = |it: PathBuf| it.is_absolute().then_some(it);
+    std::env::var_os("XDG_STATE_HOME")
+        .and_then(|it| absolute(PathBuf::from(it)))
+        .or_else(|| std::env::var_os("HOME").and_then(|it| absolute(PathBuf::from(it).join(".local/state"))))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("iot-device")
+}
+
+/// Periodic `HealthMessage` with uptime, error counters and board statistics.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    //roots are configurable so the statistics can be read from a copy of another board
+    pub thermal_root: PathBuf,
+    pub proc_root: PathBuf,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            enabled: true,
+            interval_secs: 60,
+            thermal_root: PathBuf::from("/sys/class/thermal"),
+            proc_root: PathBuf::from("/proc"),
+        }
+    }
+}
+
+/// `ActivityMesssage` published by the net connector on its own task, independent of the sensors.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            enabled: true,
+            interval_secs: 10,
+        }
+    }
+}
+
+/// What happens on SIGINT/SIGTERM.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    //how long queued telemetry may take to reach the broker before the device gives up
+    pub flush_timeout_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            flush_timeout_secs: 5,
+        }
+    }
+}
+
+/// Limits of the blocking hardware requests, a device over the limit is reported as hung.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    pub sensor_read_secs: u64,
+    //a full e-paper refresh takes a few seconds
+    pub display_refresh_secs: u64,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        TimeoutsConfig {
+            sensor_read_secs: 5,
+            display_refresh_secs: 30,
+        }
+    }
+}
+
+/// Synthetic sensors of `--simulate`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    //probability (0.0-1.0) that a simulated read starts a dropout
+    pub dropout_rate: f64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig { dropout_rate: 0.02 }
+    }
+}
+
+impl DeviceConfig {
+    /// Reads the file (when given), applies command line overrides and validates the result.
+    pub fn load(args: &ProgramArgs) -> DeviceResult<DeviceConfig> {
+        let mut config = match args.config.as_ref() {
+            Some(path) => DeviceConfig::from_file(path)?,
+            None => DeviceConfig::default(),
+        };
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> DeviceResult<DeviceConfig> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| DeviceError::io(format!("read config {}", path.display()), err))?;
+        let config = toml::from_str(&content)
+            .map_err(|err| DeviceError::Config(format!("Invalid config {}: {}", path.display(), err)))?;
+        Ok(config)
+    }
+
+    pub fn apply_args(&mut self, args: &ProgramArgs) {
+        if let Some(id_device) = args.id_device.as_ref() {
+            self.device.id = id_device.clone();
+        }
+        if let Some(host) = args.host_mqqt.as_ref() {
+            self.broker.host = host.clone();
+        }
+        if let Some(port) = args.port_mqqt {
+            self.broker.port = port;
+        }
+        if let Some(username) = args.username_mqqt.as_ref() {
+            self.broker.username = username.clone();
+        }
+        if let Some(password) = args.password_mqqt.as_ref() {
+            self.broker.password = password.clone();
+        }
+        let tls = &mut self.broker.tls;
+        if args.tls || args.ca_file.is_some() {
+            tls.enabled = true;
+        }
+        if let Some(ca_file) = args.ca_file.as_ref() {
+            tls.ca_file = Some(ca_file.clone());
+        }
+        if let Some(client_cert) = args.client_cert.as_ref() {
+            tls.client_cert_file = Some(client_cert.clone());
+        }
+        if let Some(client_key) = args.client_key.as_ref() {
+            tls.client_key_file = Some(client_key.clone());
+        }
+        if let Some(server_name) = args.tls_server_name.as_ref() {
+            tls.server_name = Some(server_name.clone());
+        }
+        if args.tls_insecure {
+            tls.insecure_skip_verify = true;
+        }
+
+        if let Some(path) = args.record.as_ref() {
+            self.recorder.path = Some(path.clone());
+        }
+        if let Some(max_bytes) = args.record_max_bytes {
+            self.recorder.max_bytes = max_bytes;
+        }
+        if let Some(keep_files) = args.record_keep {
+            self.recorder.keep_files = keep_files;
+        }
+
+        if args.autodetect {
+            self.sensors.autodetect = true;
+        }
+        if let Some(dropout_rate) = args.dropout_rate {
+            self.simulation.dropout_rate = dropout_rate;
+        }
+        //simulated and replayed runs are usually started by a normal user, who cannot write the board's
+        //state directory, and their backlog must not end up on the broker as the board's
+        if (args.simulate || args.replay.is_some()) && self.queue.path == QueueConfig::default().path {
+            self.queue.path = user_state_dir().join("queue");
+        }
+    }
+
+    /// Checks the values that would otherwise fail deep inside the engine, all problems are reported at once.
+    pub fn validate(&self) -> DeviceResult<()> {
+        let mut problems: Vec<String> = Vec::new();
+
+        if self.device.id.is_empty() {
+            problems.push("device.id must not be empty".into());
+        }
+        if self.device.id.contains(['/', '+', '#', '.']) {
+            problems.push(format!(
+                "device.id '{}' must not contain '/', '+', '#' or '.'",
+                self.device.id
+            ));
+        }
+
+        if self.broker.host.is_empty() {
+            problems.push("broker.host must not be empty".into());
+        }
+        if self.broker.port == 0 {
+            problems.push("broker.port must not be 0".into());
+        }
+        if self.broker.keep_alive_secs == 0 {
+            problems.push("broker.keep_alive_secs must be greater than 0".into());
+        }
+
+        if let Some(Err(err)) = self.broker.tls.settings().map(|it| it.load()) {
+            problems.push(err.to_string());
+        }
+
+        for (name, topic) in [
+            ("topics.telemetry", &self.topics.telemetry),
+            ("topics.receive", &self.topics.receive),
+            ("topics.global", &self.topics.global),
+            ("topics.health", &self.topics.health),
+            ("topics.activity", &self.topics.activity),
+            ("topics.presence", &self.topics.presence),
+        ] {
+            if topic.is_empty() {
+                problems.push(format!("{} must not be empty", name));
+            }
+        }
+        for (name, topic) in [
+            ("topics.telemetry", &self.topics.telemetry),
+            ("topics.health", &self.topics.health),
+            ("topics.activity", &self.topics.activity),
+            ("topics.presence", &self.topics.presence),
+        ] {
+            if topic.contains(['+', '#']) {
+                problems.push(format!("{} must not contain wildcards", name));
+            }
+        }
+
+        for (name, value) in [
+            ("intervals.print_secs", self.intervals.print_secs),
+            ("intervals.display_secs", self.intervals.display_secs),
+            ("intervals.send_secs", self.intervals.send_secs),
+            ("intervals.stale_secs", self.intervals.stale_secs),
+            ("sensors.aht20.interval_secs", self.sensors.aht20.interval_secs),
+            ("sensors.bmp280.interval_secs", self.sensors.bmp280.interval_secs),
+            ("sensors.dht22.interval_secs", self.sensors.dht22.interval_secs),
+            ("sensors.ds18b20.interval_secs", self.sensors.ds18b20.interval_secs),
+            ("health.interval_secs", self.health.interval_secs),
+            ("heartbeat.interval_secs", self.heartbeat.interval_secs),
+            ("timeouts.sensor_read_secs", self.timeouts.sensor_read_secs),
+            ("timeouts.display_refresh_secs", self.timeouts.display_refresh_secs),
+        ] {
+            if value == 0 {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+
+        if self.sensors.autodetect && self.sensors.i2c_buses.is_empty() {
+            problems.push("sensors.i2c_buses must not be empty when sensors.autodetect is set".into());
+        }
+        if self.sensors.aht20.address > 0x7f {
+            problems.push(format!(
+                "sensors.aht20.address {:#x} is not a 7-bit I2C address",
+                self.sensors.aht20.address
+            ));
+        }
+        if !matches!(self.sensors.bmp280.address, 0x76 | 0x77) {
+            problems.push(format!(
+                "sensors.bmp280.address {:#x} must be 0x76 or 0x77",
+                self.sensors.bmp280.address
+            ));
+        }
+        if self.sensors.dht22.enabled
+            && (self.sensors.dht22.temp_path.is_empty() || self.sensors.dht22.humidity_path.is_empty())
+        {
+            problems.push("sensors.dht22 paths must not be empty".into());
+        }
+        for (index, iio) in self.sensors.iio.iter().enumerate() {
+            if iio.id.is_empty() || iio.device.is_empty() {
+                problems.push(format!("sensors.iio[{}] needs an id and a device", index));
+            }
+            if iio.interval_secs == 0 {
+                problems.push(format!("sensors.iio[{}].interval_secs must be greater than 0", index));
+            }
+            let reserved = ["aht20", "bmp280", "dht22"].contains(&iio.id.as_str()) || ds18b20::is_probe(&iio.id);
+            if reserved || self.sensors.iio[..index].iter().any(|it| it.id == iio.id) {
+                problems.push(format!("sensors.iio[{}].id '{}' is already used", index, iio.id));
+            }
+        }
+
+        if self.display.enabled {
+            let display = &self.display;
+            if display.spi_bus > 6 {
+                problems.push(format!("display.spi_bus {} does not exist", display.spi_bus));
+            }
+            let refresh = &display.refresh;
+            for (name, deadband) in [
+                ("temperature_deadband", refresh.temperature_deadband),
+                ("humidity_deadband", refresh.humidity_deadband),
+                ("pressure_deadband", refresh.pressure_deadband),
+            ] {
+                if !(deadband.is_finite() && deadband >= 0.0) {
+                    problems.push(format!("display.refresh.{} {} must be 0 or more", name, deadband));
+                }
+            }
+            if display.pages.is_empty() {
+                problems.push("display.pages must not be empty".into());
+            }
+            if display.pages.len() > 1 && display.page_secs == 0 {
+                problems.push("display.page_secs must be greater than 0".into());
+            }
+            if let Some(path) = &display.snapshot_path {
+                if !matches!(path.extension().and_then(|it| it.to_str()), Some("png" | "pbm")) {
+                    problems.push(format!("display.snapshot_path {} must end in .png or .pbm", path.display()));
+                }
+            }
+            if display.driver == DisplayDriver::Ssd1680 && display.interface == DisplayInterface::I2c && !display.headless {
+                problems.push("display.interface must be spi for the ssd1680".into());
+            }
+            if display.driver != DisplayDriver::Ssd1680 && display.oled_size().is_none() {
+                problems.push(format!(
+                    "display.size '{}' is not supported by the {:?}",
+                    display.size, display.driver
+                ));
+            }
+            if let Some(rotation) = display.rotation {
+                if !matches!(rotation, 0 | 90 | 180 | 270) {
+                    problems.push(format!("display.rotation {} must be 0, 90, 180 or 270", rotation));
+                }
+            }
+            if display.interface == DisplayInterface::I2c && !display.headless && display.i2c_address > 0x7f {
+                problems.push(format!(
+                    "display.i2c_address {:#x} is not a 7-bit I2C address",
+                    display.i2c_address
+                ));
+            }
+            //I2C modules need no GPIOs, busy only exists on the e-paper, headless needs none at all
+            let mut pins = Vec::new();
+            if display.interface == DisplayInterface::Spi && !display.headless {
+                pins.extend([
+                    ("cs_pin", display.cs_pin),
+                    ("dc_pin", display.dc_pin),
+                    ("rst_pin", display.rst_pin),
+                ]);
+            }
+            if display.driver == DisplayDriver::Ssd1680 && !display.headless {
+                pins.push(("busy_pin", display.busy_pin));
+            }
+            for (index, (name, pin)) in pins.iter().enumerate() {
+                if *pin > 27 {
+                    problems.push(format!("display.{} {} is not a header GPIO (0-27)", name, pin));
+                }
+                if let Some((other, _)) = pins[..index].iter().find(|(_, it)| it == pin) {
+                    problems.push(format!("display.{} and display.{} both use GPIO {}", other, name, pin));
+                }
+            }
+        }
+
+        if self.queue.enabled {
+            if !self.queue.path.is_absolute() {
+                problems.push(format!(
+                    "queue.path \"{}\" must be absolute, a service runs in /",
+                    self.queue.path.display()
+                ));
+            }
+            if self.queue.max_messages == 0 {
+                problems.push("queue.max_messages must be greater than 0".into());
+            }
+        }
+
+        let dropout_rate = self.simulation.dropout_rate;
+        if !(0.0..=1.0).contains(&dropout_rate) {
+            problems.push(format!("simulation.dropout_rate {} must be between 0.0 and 1.0", dropout_rate));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(DeviceError::Config(format!(
+                "Invalid configuration:\n  {}",
+                problems.join("\n  ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn args(flags: &[&str]) -> ProgramArgs {
+        ProgramArgs::parse_from(std::iter::once("iot-device").chain(flags.iter().copied()))
+    }
+
+    fn write(content: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("device.toml");
+        fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    fn problems(config: &DeviceConfig) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn the_example_file_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("device.example.toml");
+        let config = DeviceConfig::load(&args(&["--config", path.to_str().unwrap()])).unwrap();
+        assert_eq!(config.device.id, "air");
+        assert_eq!(config.queue.path, PathBuf::from("/var/lib/iot-device/queue"));
+    }
+
+    #[test]
+    fn missing_sections_keep_the_defaults() {
+        let (_dir, path) = write("[broker]\nhost = \"rabbitmq.local\"\n");
+        let config = DeviceConfig::from_file(&path).unwrap();
+        let defaults = DeviceConfig::default();
+
+        assert_eq!(config.broker.host, "rabbitmq.local");
+        //the rest of the section and every other section fall back to the defaults
+        assert_eq!(config.broker.port, defaults.broker.port);
+        assert_eq!(config.device.id, defaults.device.id);
+        assert_eq!(config.intervals.send_secs, defaults.intervals.send_secs);
+        assert_eq!(config.display.refresh.max_partial_refreshes, 10);
+        assert_eq!(config.queue.max_messages, defaults.queue.max_messages);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn unknown_keys_are_named() {
+        let (_dir, path) = write("[broker]\nhots = \"rabbitmq.local\"\n");
+        let err = DeviceConfig::from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("hots"), "{}", err);
+
+        let (_dir, path) = write("[sensor]\nautodetect = true\n");
+        let err = DeviceConfig::from_file(&path).unwrap_err().to_string();
+        assert!(err.contains("sensor"), "{}", err);
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let (_dir, path) = write("[device]\nid = \"greenhouse\"\n[broker]\nhost = \"rabbitmq.local\"\n");
+        let path = path.to_str().unwrap();
+
+        //apply_args alone, validate would read the CA file
+        let mut config = DeviceConfig::from_file(Path::new(path)).unwrap();
+        config.apply_args(&args(&["--port-mqqt", "8883", "--ca-file", "/ca.pem"]));
+        assert_eq!(config.broker.port, 8883);
+        //not given on the command line, the file value stays
+        assert_eq!(config.broker.host, "rabbitmq.local");
+        assert_eq!(config.device.id, "greenhouse");
+        //--ca-file implies TLS
+        assert!(config.broker.tls.enabled);
+        assert_eq!(config.broker.tls.ca_file, Some(PathBuf::from("/ca.pem")));
+
+        let config = DeviceConfig::load(&args(&["--config", path, "--id-device", "shed"])).unwrap();
+        assert_eq!(config.device.id, "shed");
+        assert_eq!(config.topics.resolve("shed").telemetry, "iotserver/shed/sendtelemetry");
+    }
+
+    #[test]
+    fn validate_reports_every_problem_at_once() {
+        let mut config = DeviceConfig::default();
+        config.device.id = "a/b".to_string();
+        config.broker.port = 0;
+        config.intervals.send_secs = 0;
+        config.queue.path = PathBuf::from("queue");
+
+        let err = problems(&config);
+        assert!(err.starts_with("Invalid configuration:"), "{}", err);
+        for expected in [
+            "device.id 'a/b' must not contain",
+            "broker.port must not be 0",
+            "send_secs must be greater than 0",
+            "queue.path \"queue\" must be absolute",
+        ] {
+            assert!(err.contains(expected), "{} not in {}", expected, err);
+        }
+        assert_eq!(err.lines().count(), 5, "{}", err);
+    }
+
+    #[test]
+    fn dropout_rate_has_to_be_a_probability() {
+        for dropout_rate in [f64::NAN, f64::INFINITY, -0.1, 1.5] {
+            let mut config = DeviceConfig::default();
+            config.simulation.dropout_rate = dropout_rate;
+            assert!(problems(&config).contains("simulation.dropout_rate"), "{}", dropout_rate);
+        }
+
+        let config = DeviceConfig::load(&args(&["--simulate", "--dropout-rate", "1"])).unwrap();
+        assert_eq!(config.simulation.dropout_rate, 1.0);
+        assert!(DeviceConfig::load(&args(&["--simulate", "--dropout-rate", "nan"])).is_err());
+    }
+
+    #[test]
+    fn every_device_gets_its_own_queue_directory() {
+        let queue = QueueConfig {
+            path: PathBuf::from("/var/lib/iot-device/queue"),
+            ..QueueConfig::default()
+        };
+        assert_eq!(queue.dir("air"), PathBuf::from("/var/lib/iot-device/queue/air"));
+        assert_ne!(queue.dir("air"), queue.dir("shed"));
+    }
+
+    #[test]
+    fn simulated_runs_queue_in_the_user_state_directory() {
+        let config = DeviceConfig::load(&args(&["--simulate"])).unwrap();
+        assert_eq!(config.queue.path, user_state_dir().join("queue"));
+        assert_ne!(config.queue.path, QueueConfig::default().path);
+
+        //an explicitly configured path is kept
+        let (_dir, path) = write("[queue]\npath = \"/srv/queue\"\n");
+        let config = DeviceConfig::load(&args(&["--config", path.to_str().unwrap(), "--simulate"])).unwrap();
+        assert_eq!(config.queue.path, PathBuf::from("/srv/queue"));
+
+        let config = DeviceConfig::load(&args(&[])).unwrap();
+        assert_eq!(config.queue.path, QueueConfig::default().path);
+    }
+
+    #[test]
+    fn insecure_tls_needs_no_ca_file() {
+        let config = DeviceConfig::load(&args(&["--tls", "--tls-insecure"])).unwrap();
+        let settings = config.broker.tls.settings().unwrap();
+        assert_eq!(settings.ca_file, None);
+        assert!(settings.insecure_skip_verify);
+
+        let config = DeviceConfig::load(&args(&["--ca-file", "/nonexistent/ca.pem"]));
+        assert!(config.unwrap_err().to_string().contains("/nonexistent/ca.pem"));
+    }
+}
@@ -0,0 +1,187 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::geometry::Size;
+use rppal::gpio::InputPin;
+use rppal::gpio::OutputPin;
+use rppal::spi::Spi;
+use ssd1680::prelude::*;
+
+use super::{open_gpio, open_spi, output_pin, panel_size, render, DisplayBackend, Screen};
+use crate::engine::config::DisplayConfig;
+use crate::error::{DeviceError, DeviceResult};
+
+//SSD1680 commands, ssd1680 0.1.0 only runs the full update sequence, so the controller is driven here
+//and the crate draws the rotated frame buffer
+const DRIVER_OUTPUT_CONTROL: u8 = 0x01;
+const DATA_ENTRY_MODE: u8 = 0x11;
+const SW_RESET: u8 = 0x12;
+const TEMPERATURE_SENSOR: u8 = 0x18;
+const MASTER_ACTIVATION: u8 = 0x20;
+const DISPLAY_UPDATE_CONTROL_1: u8 = 0x21;
+const DISPLAY_UPDATE_CONTROL_2: u8 = 0x22;
+const WRITE_RAM_BW: u8 = 0x24;
+const WRITE_RAM_RED: u8 = 0x26;
+const BORDER_WAVEFORM: u8 = 0x3C;
+const RAM_X_RANGE: u8 = 0x44;
+const RAM_Y_RANGE: u8 = 0x45;
+const RAM_X_COUNTER: u8 = 0x4E;
+const RAM_Y_COUNTER: u8 = 0x4F;
+
+//update sequences with the waveforms from the OTP: display mode 1 redraws every pixel, display mode 2
+//only drives the pixels that differ between the BW RAM and the previous frame kept in the red RAM
+const UPDATE_FULL: u8 = 0xF7;
+const UPDATE_PARTIAL: u8 = 0xFF;
+//the border follows the LUT on full refreshes and is left alone on partial ones
+const BORDER_FULL: u8 = 0x05;
+const BORDER_PARTIAL: u8 = 0x80;
+
+//a full refresh takes about 2 s, longer in the cold
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// SSD1680 2.13" 250x122 black and white e-paper on SPI.
+pub struct EpaperDisplay {
+    spi: Spi,
+    cs: OutputPin,
+    busy: InputPin,
+    dc: OutputPin,
+    rst: OutputPin,
+    rotation: DisplayRotation,
+    size: Size,
+}
+
+impl EpaperDisplay {
+    pub fn new(config: &DisplayConfig) -> DeviceResult<Self> {
+        let gpio = open_gpio()?;
+        let spi = open_spi(config)?;
+
+        let cs = output_pin(&gpio, "cs", config.cs_pin)?;
+        let busy = gpio
+            .get(config.busy_pin)
+            .map_err(|source| DeviceError::Gpio {
+                context: format!("busy pin {}", config.busy_pin),
+                source,
+            })?
+            .into_input();
+        let dc = output_pin(&gpio, "dc", config.dc_pin)?;
+        let rst = output_pin(&gpio, "rst", config.rst_pin)?;
+
+        let rotation = match config.rotation() {
+            0 => DisplayRotation::Rotate0,
+            90 => DisplayRotation::Rotate90,
+            180 => DisplayRotation::Rotate180,
+            _ => DisplayRotation::Rotate270,
+        };
+        let mut display = Self {
+            spi,
+            cs,
+            busy,
+            dc,
+            rst,
+            rotation,
+            size: panel_size(config),
+        };
+        display
+            .init()
+            .map_err(|err| DeviceError::Display(format!("SSD1680 init failed: {}", err)))?;
+        Ok(display)
+    }
+
+    //the init sequence of ssd1680 0.1.0
+    fn init(&mut self) -> DeviceResult<()> {
+        self.rst.set_low();
+        thread::sleep(Duration::from_millis(10));
+        self.rst.set_high();
+        thread::sleep(Duration::from_millis(10));
+
+        self.command(SW_RESET, &[])?;
+        self.wait_until_idle()?;
+        self.command(DRIVER_OUTPUT_CONTROL, &[ssd1680::HEIGHT - 1, 0x00, 0x00])?;
+        //x then y increment
+        self.command(DATA_ENTRY_MODE, &[0b11])?;
+        self.command(BORDER_WAVEFORM, &[BORDER_FULL])?;
+        self.command(TEMPERATURE_SENSOR, &[0x80])?;
+        self.command(DISPLAY_UPDATE_CONTROL_1, &[0x00, 0x80])?;
+        self.wait_until_idle()
+    }
+
+    fn frame(&self, screen: &Screen) -> Display2in13 {
+        let mut display_bw = Display2in13::bw();
+        display_bw.set_rotation(self.rotation);
+        render::render(screen, self.size, &mut display_bw);
+        display_bw
+    }
+
+    fn write_ram(&mut self, ram: u8, buffer: &[u8]) -> DeviceResult<()> {
+        self.command(RAM_X_RANGE, &[0, (ssd1680::WIDTH - 1) >> 3])?;
+        self.command(RAM_Y_RANGE, &[0, 0, ssd1680::HEIGHT - 1, 0])?;
+        self.command(RAM_X_COUNTER, &[0])?;
+        self.command(RAM_Y_COUNTER, &[0, 0])?;
+        self.command(ram, buffer)
+    }
+
+    fn update(&mut self, sequence: u8) -> DeviceResult<()> {
+        self.command(DISPLAY_UPDATE_CONTROL_2, &[sequence])?;
+        self.command(MASTER_ACTIVATION, &[])?;
+        self.wait_until_idle()
+    }
+
+    //DC low for the command byte, high for its data
+    fn command(&mut self, command: u8, data: &[u8]) -> DeviceResult<()> {
+        self.cs.set_low();
+        self.dc.set_low();
+        let mut result = self.spi.write(&[command]);
+        if result.is_ok() && !data.is_empty() {
+            self.dc.set_high();
+            result = self.spi.write(data);
+        }
+        self.cs.set_high();
+        result.map(|_| ()).map_err(|source| DeviceError::Spi {
+            context: format!("SSD1680 command {:#04x}", command),
+            source,
+        })
+    }
+
+    fn wait_until_idle(&mut self) -> DeviceResult<()> {
+        let started = Instant::now();
+        while self.busy.is_high() {
+            if started.elapsed() > BUSY_TIMEOUT {
+                return Err(DeviceError::Display(format!(
+                    "SSD1680 still busy after {} s",
+                    BUSY_TIMEOUT.as_secs()
+                )));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+}
+
+impl DisplayBackend for EpaperDisplay {
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn show(&mut self, screen: &Screen) -> DeviceResult<()> {
+        let frame = self.frame(screen);
+        self.command(BORDER_WAVEFORM, &[BORDER_FULL])?;
+        //the red RAM keeps the frame the next partial refresh compares against
+        self.write_ram(WRITE_RAM_BW, frame.buffer())?;
+        self.write_ram(WRITE_RAM_RED, frame.buffer())?;
+        self.update(UPDATE_FULL)
+            .map_err(|err| DeviceError::Display(format!("refresh failed: {}", err)))
+    }
+
+    fn partial_refresh(&self) -> bool {
+        true
+    }
+
+    fn show_partial(&mut self, screen: &Screen) -> DeviceResult<()> {
+        let frame = self.frame(screen);
+        self.command(BORDER_WAVEFORM, &[BORDER_PARTIAL])?;
+        self.write_ram(WRITE_RAM_BW, frame.buffer())?;
+        self.update(UPDATE_PARTIAL)
+            .map_err(|err| DeviceError::Display(format!("partial refresh failed: {}", err)))?;
+        self.write_ram(WRITE_RAM_RED, frame.buffer())
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+
+use crate::error::{DeviceError, DeviceResult};
+
+/// 1-bit drawing buffer in screen coordinates.
+///
+/// `render` draws into it for controllers whose driver crate is built on another embedded-graphics
+/// version, the backend then copies it pixel by pixel into the driver's own buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    //row major, true for ink / lit pixels
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    pub fn new(size: Size) -> Framebuffer {
+        Framebuffer {
+            width: size.width,
+            height: size.height,
+            pixels: vec![false; (size.width * size.height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Every pixel as (x, y, on), row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, bool)> + '_ {
+        let width = self.width;
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(index, on)| (index as u32 % width, index as u32 / width, *on))
+    }
+
+    /// Writes the buffer as .png or .pbm, on pixels are black like ink on the e-paper.
+    pub fn save(&self, path: &Path) -> DeviceResult<()> {
+        let write = match path.extension().and_then(|it| it.to_str()) {
+            Some("png") => Framebuffer::write_png,
+            Some("pbm") => Framebuffer::write_pbm,
+            _ => {
+                return Err(DeviceError::Config(format!(
+                    "Snapshot file has to be .png or .pbm: {}",
+                    path.display()
+                )))
+            }
+        };
+        let file = File::create(path)
+            .map_err(|err| DeviceError::io(format!("create snapshot {}", path.display()), err))?;
+        write(self, BufWriter::new(file))
+            .map_err(|err| DeviceError::io(format!("write snapshot {}", path.display()), err))
+    }
+
+    /// Reads a P4 .pbm like the ones `save` writes.
+    pub fn load_pbm(path: &Path) -> DeviceResult<Framebuffer> {
+        let bytes = fs::read(path).map_err(|err| DeviceError::io(format!("read {}", path.display()), err))?;
+        let invalid = |reason: &str| DeviceError::Config(format!("{} is not a P4 .pbm: {}", path.display(), reason));
+
+        //"P4", width and height separated by whitespace or comments, then one whitespace byte before the data
+        let mut position = 0;
+        let mut header = Vec::new();
+        while header.len() < 3 {
+            while position < bytes.len() && (bytes[position].is_ascii_whitespace() || bytes[position] == b'#') {
+                if bytes[position] == b'#' {
+                    while position < bytes.len() && bytes[position] != b'\n' {
+                        position += 1;
+                    }
+                } else {
+                    position += 1;
+                }
+            }
+            let start = position;
+            while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if start == position {
+                return Err(invalid("truncated header"));
+            }
+            header.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+        }
+        if header[0] != "P4" {
+            return Err(invalid("wrong magic"));
+        }
+        let width: u32 = header[1].parse().map_err(|_| invalid("bad width"))?;
+        let height: u32 = header[2].parse().map_err(|_| invalid("bad height"))?;
+        let data = bytes.get(position + 1..).unwrap_or_default();
+
+        let row_bytes = (width as usize).div_ceil(8);
+        if data.len() < row_bytes * height as usize {
+            return Err(invalid("truncated data"));
+        }
+        let mut framebuffer = Framebuffer::new(Size::new(width, height));
+        for y in 0..height {
+            for x in 0..width {
+                let byte = data[y as usize * row_bytes + x as usize / 8];
+                framebuffer.pixels[(y * width + x) as usize] = byte & (0x80 >> (x % 8)) != 0;
+            }
+        }
+        Ok(framebuffer)
+    }
+
+    //rows packed 8 pixels per byte, most significant bit first, each row padded to a whole byte
+    fn packed_rows(&self, on_bit: bool) -> Vec<u8> {
+        let row_bytes = (self.width as usize).div_ceil(8);
+        let mut packed = vec![if on_bit { 0x00 } else { 0xff }; row_bytes * self.height as usize];
+        for (x, y, on) in self.pixels() {
+            if on {
+                let byte = &mut packed[y as usize * row_bytes + x as usize / 8];
+                let bit = 0x80 >> (x % 8);
+                if on_bit {
+                    *byte |= bit;
+                } else {
+                    *byte &= !bit;
+                }
+            }
+        }
+        packed
+    }
+
+    //P4, a set bit is black
+    fn write_pbm(&self, mut out: BufWriter<File>) -> std::io::Result<()> {
+        write!(out, "P4\n{} {}\n", self.width, self.height)?;
+        out.write_all(&self.packed_rows(true))?;
+        out.flush()
+    }
+
+    //1-bit grayscale, a set bit is white
+    fn write_png(&self, out: BufWriter<File>) -> std::io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.packed_rows(false))?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl DrawTarget<BinaryColor> for Framebuffer {
+    type Error = core::convert::Infallible;
+
+    //pixels outside the buffer are clipped like on the panels
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> Result<(), Self::Error> {
+        let Pixel(point, color) = pixel;
+        if point.x < 0 || point.y < 0 {
+            return Ok(());
+        }
+        let (x, y) = (point.x as u32, point.y as u32);
+        if x < self.width && y < self.height {
+            self.pixels[(y * self.width + x) as usize] = color.is_on();
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
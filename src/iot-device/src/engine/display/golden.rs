@@ -0,0 +1,257 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use embedded_graphics::geometry::{Point, Size};
+
+use super::render::{self, Element};
+use super::{Framebuffer, Screen};
+use crate::engine::history::History;
+use crate::engine::sensors::{Quantity, Reading};
+use crate::engine::ResultTable;
+
+/// A screen on one panel size, its rendering is kept as `<name>.pbm` in the reference directory.
+struct Case {
+    name: String,
+    size: Size,
+    screen: Screen,
+}
+
+//next to Cargo.toml, so the test finds them from any working directory
+fn reference_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+/// Renders every case, checks the layout and compares the pixels with `golden/<case>.pbm`.
+///
+/// With `BLESS=1` the current renderings are written as the new references instead, after an intended
+/// layout change.
+#[test]
+fn display_layouts_match_the_references() {
+    let dir = reference_dir();
+    let bless = std::env::var_os("BLESS").is_some_and(|it| it == "1");
+
+    let mut failures = Vec::new();
+    for case in cases() {
+        let mut problems = layout_problems(&render::elements(&case.screen, case.size), case.size);
+
+        let mut actual = Framebuffer::new(case.size);
+        render::render(&case.screen, case.size, &mut actual);
+        let path = dir.join(format!("{}.pbm", case.name));
+        //a broken layout is never blessed
+        if bless && problems.is_empty() {
+            if let Err(err) = actual.save(&path) {
+                problems.push(err.to_string());
+            }
+        } else if !bless {
+            match Framebuffer::load_pbm(&path) {
+                Ok(expected) => problems.extend(diff(&expected, &actual)),
+                Err(err) => problems.push(format!("{}, run with BLESS=1 to create it", err)),
+            }
+        }
+
+        if !problems.is_empty() {
+            failures.push(format!("FAIL {}", case.name));
+            failures.extend(problems.iter().map(|problem| format!("  {}", problem)));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+fn cases() -> Vec<Case> {
+    use Quantity::*;
+
+    let air = vec![
+        ("aht20", Temperature, Some(21.5)),
+        ("aht20", Humidity, Some(45.25)),
+        ("bmp280", Temperature, Some(22.0)),
+        ("bmp280", Pressure, Some(101.33)),
+    ];
+    let mut probes = air.clone();
+    probes.extend([
+        ("28-0316a2795aff", Temperature, Some(18.06)),
+        ("28-0416b1c2d3e4", Temperature, None),
+    ]);
+    let serials: Vec<String> = (0..9).map(|index| format!("28-0316a27950a{}", index)).collect();
+    let mut many_probes = air.clone();
+    many_probes.extend(serials.iter().zip(15..).map(|(serial, value)| (serial.as_str(), Temperature, Some(value as f32))));
+    //the widest values a sensor reports, negative temperatures do not fit the e-paper with the label
+    let extremes = vec![
+        ("aht20", Temperature, Some(-40.25)),
+        ("aht20", Humidity, Some(100.0)),
+        ("bmp280", Pressure, Some(108.47)),
+    ];
+    let stopped = Screen::Stopped {
+        date: "2024-03-01".to_string(),
+        time: "12:34:56 UTC".to_string(),
+    };
+    let values = |demo_switch, readings: &[(&str, Quantity, Option<f32>)]| Screen::values(&table(demo_switch, readings));
+    let sensors = Screen::sensors(&table(false, &probes));
+    let history = day_history();
+    let noon = UNIX_EPOCH + Duration::from_secs(19_783 * 24 * 60 * 60 + 12 * 60 * 60);
+    let status = Screen::status(
+        "greenhouse-2",
+        Some(IpAddr::V4(Ipv4Addr::new(192, 168, 100, 201))),
+        Some(true),
+        Some(noon),
+    );
+
+    let epaper = Size::new(250, 122);
+    let mut cases = vec![
+        ("epaper_values".to_string(), epaper, values(false, &air)),
+        ("epaper_probes_demo".to_string(), epaper, values(true, &probes)),
+        ("epaper_many_probes".to_string(), epaper, values(false, &many_probes)),
+        ("epaper_extremes".to_string(), epaper, values(false, &extremes)),
+        ("epaper_unavailable".to_string(), epaper, values(false, &[("aht20", Temperature, None)])),
+        ("epaper_stopped".to_string(), epaper, stopped.clone()),
+        ("epaper_sensors".to_string(), epaper, sensors.clone()),
+        ("epaper_history".to_string(), epaper, Screen::history(&history)),
+        ("epaper_history_empty".to_string(), epaper, Screen::history(&History::default())),
+        ("epaper_min_max".to_string(), epaper, Screen::min_max(&history, noon)),
+        ("epaper_status".to_string(), epaper, status.clone()),
+        ("epaper_status_offline".to_string(), epaper, Screen::status("air", None, Some(false), None)),
+    ];
+    //every OLED resolution the drivers support
+    for (width, height) in [(132, 64), (128, 64), (128, 32), (96, 16), (72, 40), (64, 48)] {
+        let size = Size::new(width, height);
+        let name = format!("oled_{}x{}", width, height);
+        cases.push((format!("{}_probes_demo", name), size, values(true, &probes)));
+        cases.push((format!("{}_extremes", name), size, values(false, &extremes)));
+        cases.push((format!("{}_stopped", name), size, stopped.clone()));
+        cases.push((format!("{}_sensors", name), size, sensors.clone()));
+        cases.push((format!("{}_history", name), size, Screen::history(&history)));
+        cases.push((format!("{}_min_max", name), size, Screen::min_max(&history, noon)));
+        cases.push((format!("{}_status", name), size, status.clone()));
+    }
+    cases
+        .into_iter()
+        .map(|(name, size, screen)| Case { name, size, screen })
+        .collect()
+}
+
+//the day up to noon of 2024-03-01 UTC, a reading a minute with an hour of failed reads in the morning
+fn day_history() -> History {
+    let midnight = UNIX_EPOCH + Duration::from_secs(19_783 * 24 * 60 * 60);
+    let mut history = History::default();
+    for minute in 0..12 * 60 {
+        let at = midnight + Duration::from_secs(minute * 60);
+        if (7 * 60..8 * 60).contains(&minute) {
+            history.record(at, None, None, None);
+            continue;
+        }
+        //coldest at 4 in the morning and drying out towards noon, straight lines so the rendering does not depend on libm
+        let hours = minute as f32 / 60.0;
+        let temperature = 14.0 + (hours - 4.0).abs() * 0.8;
+        let humidity = 70.0 - hours * 2.5;
+        history.record(at, Some(temperature), Some(humidity), Some(101.2 + hours * 0.01));
+    }
+    history
+}
+
+//a reading of None registers the sensor without a value, like one that has not answered yet
+fn table(demo_switch: bool, readings: &[(&str, Quantity, Option<f32>)]) -> ResultTable {
+    let mut result_table = ResultTable {
+        demo_switch,
+        ..ResultTable::default()
+    };
+    for (sensor_id, quantity, value) in readings {
+        result_table.register(sensor_id, &[*quantity]);
+        if let Some(value) = value {
+            result_table.apply(sensor_id, &[Reading::new(*quantity, *value)]);
+        }
+    }
+    result_table
+}
+
+//text cut off by the panel edge, or elements drawn over each other
+fn layout_problems(elements: &[Element], size: Size) -> Vec<String> {
+    let mut problems = Vec::new();
+    let panel = (Point::zero(), Point::new(size.width as i32 - 1, size.height as i32 - 1));
+    for (index, element) in elements.iter().enumerate() {
+        let bounds = element.bounds();
+        if bounds.0.x < panel.0.x || bounds.0.y < panel.0.y || bounds.1.x > panel.1.x || bounds.1.y > panel.1.y {
+            problems.push(format!("{} overflows the {}x{} panel", describe(element), size.width, size.height));
+        }
+        for other in &elements[..index] {
+            //the segments of a sparkline meet at their ends
+            if matches!((element, other), (Element::Line { .. }, Element::Line { .. })) {
+                continue;
+            }
+            let other_bounds = other.bounds();
+            let overlap = bounds.0.x <= other_bounds.1.x
+                && other_bounds.0.x <= bounds.1.x
+                && bounds.0.y <= other_bounds.1.y
+                && other_bounds.0.y <= bounds.1.y;
+            if overlap {
+                problems.push(format!("{} overlaps {}", describe(element), describe(other)));
+            }
+        }
+    }
+    problems
+}
+
+fn describe(element: &Element) -> String {
+    let (top_left, bottom_right) = element.bounds();
+    let name = match element {
+        Element::Text { text, .. } => format!("\"{}\"", text),
+        Element::Circle { .. } => "the demo circle".to_string(),
+        Element::Line { .. } => "a sparkline segment".to_string(),
+    };
+    format!(
+        "{} at ({},{})-({},{})",
+        name, top_left.x, top_left.y, bottom_right.x, bottom_right.y
+    )
+}
+
+/// The differing region as text, '+' is ink only in the new rendering, '-' ink only in the reference
+/// and '#' ink in both.
+fn diff(expected: &Framebuffer, actual: &Framebuffer) -> Option<String> {
+    if (expected.width(), expected.height()) != (actual.width(), actual.height()) {
+        return Some(format!(
+            "reference is {}x{}, rendering is {}x{}",
+            expected.width(),
+            expected.height(),
+            actual.width(),
+            actual.height()
+        ));
+    }
+
+    let changed: Vec<(u32, u32)> = actual
+        .pixels()
+        .filter(|(x, y, on)| expected.pixel(*x, *y) != *on)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    if changed.is_empty() {
+        return None;
+    }
+
+    //the changed area with a little context around it
+    let margin = 2;
+    let left = changed.iter().map(|it| it.0).min()?.saturating_sub(margin);
+    let right = (changed.iter().map(|it| it.0).max()? + margin).min(actual.width() - 1);
+    let top = changed.iter().map(|it| it.1).min()?.saturating_sub(margin);
+    let bottom = (changed.iter().map(|it| it.1).max()? + margin).min(actual.height() - 1);
+
+    let mut text = format!(
+        "{} pixels differ in x {}..={}, y {}..={} ('+' new ink, '-' missing ink, '#' unchanged ink):",
+        changed.len(),
+        left,
+        right,
+        top,
+        bottom
+    );
+    for y in top..=bottom {
+        text.push_str(&format!("\n  {:>3} ", y));
+        for x in left..=right {
+            text.push(match (expected.pixel(x, y), actual.pixel(x, y)) {
+                (true, true) => '#',
+                (false, true) => '+',
+                (true, false) => '-',
+                (false, false) => '.',
+            });
+        }
+    }
+    Some(text)
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use embedded_graphics::geometry::Size;
+
+use super::{panel_size, render, DisplayBackend, Framebuffer, Screen};
+use crate::engine::config::DisplayConfig;
+use crate::error::DeviceResult;
+
+/// Draws into memory with the geometry of the configured panel, for development and CI.
+///
+/// Every refresh is written to `snapshot_path` when one is set.
+pub struct HeadlessDisplay {
+    size: Size,
+    snapshot_path: Option<PathBuf>,
+}
+
+impl HeadlessDisplay {
+    pub fn new(config: &DisplayConfig) -> HeadlessDisplay {
+        HeadlessDisplay {
+            size: panel_size(config),
+            snapshot_path: config.snapshot_path.clone(),
+        }
+    }
+}
+
+impl DisplayBackend for HeadlessDisplay {
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn show(&mut self, screen: &Screen) -> DeviceResult<()> {
+        //rendered even without a path, so layout panics show up in development too
+        let mut framebuffer = Framebuffer::new(self.size);
+        render::render(screen, self.size, &mut framebuffer);
+        match &self.snapshot_path {
+            Some(path) => framebuffer.save(path),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{net::IpAddr, path::Path, time::SystemTime};
+
+use embedded_graphics::geometry::Size;
+use rppal::gpio::{Gpio, OutputPin};
+use rppal::spi::Spi;
+
+use super::config::{DisplayConfig, DisplayDriver};
+use super::history::{History, MinMax};
+use super::i2c_bus::I2cBusManager;
+use super::sensors::{ds18b20, Quantity};
+use super::ResultTable;
+use crate::error::{DeviceError, DeviceResult};
+
+pub mod epaper;
+pub mod framebuffer;
+#[cfg(test)]
+mod golden;
+pub mod headless;
+pub mod oled;
+pub mod refresh;
+pub mod render;
+
+pub use self::epaper::EpaperDisplay;
+pub use self::framebuffer::Framebuffer;
+pub use self::headless::HeadlessDisplay;
+pub use self::oled::{Sh1106Display, Ssd1306Display};
+pub use self::refresh::RefreshPolicy;
+
+/// Everything a refresh shows, independent of the panel it is drawn on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screen {
+    Values(Values),
+    //latest reading of every sensor in registration order
+    Sensors(Vec<SensorValues>),
+    //sparkline points oldest first, the last one is the running bucket
+    History {
+        temperature: Vec<Option<f32>>,
+        humidity: Vec<Option<f32>>,
+    },
+    MinMax(MinMax),
+    Status(Status),
+    //replaces the values on shutdown, e-paper keeps the last image after power is gone
+    Stopped { date: String, time: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Values {
+    //averages of the air sensors, None when no sensor has an available value
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+    pub pressure: Option<f32>,
+    pub demo_switch: bool,
+    //DS18B20 probes by serial, they measure water or surfaces and stay out of the averages
+    pub probes: Vec<(String, Option<f32>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorValues {
+    pub sensor_id: String,
+    pub values: Vec<(Quantity, Option<f32>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub device_id: String,
+    pub ip: Option<String>,
+    //None before the broker connection is started
+    pub connected: Option<bool>,
+    //"12:34:56 UTC" of the last telemetry the broker acknowledged
+    pub last_delivery: Option<String>,
+}
+
+impl Screen {
+    pub fn values(result_table: &ResultTable) -> Screen {
+        //failed and stale sensors are left out of the averages
+        let probes = result_table
+            .columns()
+            .iter()
+            .filter(|it| ds18b20::is_probe(&it.sensor_id))
+            .map(|it| (it.sensor_id.clone(), it.measured.available(result_table.stale_after)))
+            .collect();
+
+        Screen::Values(Values {
+            temperature: result_table.air_average(Quantity::Temperature),
+            humidity: result_table.air_average(Quantity::Humidity),
+            pressure: result_table.air_average(Quantity::Pressure),
+            demo_switch: result_table.demo_switch,
+            probes,
+        })
+    }
+
+    pub fn sensors(result_table: &ResultTable) -> Screen {
+        let mut sensors: Vec<SensorValues> = Vec::new();
+        for column in result_table.columns() {
+            let value = (column.quantity, column.measured.available(result_table.stale_after));
+            match sensors.iter_mut().find(|it| it.sensor_id == column.sensor_id) {
+                Some(sensor) => sensor.values.push(value),
+                None => sensors.push(SensorValues {
+                    sensor_id: column.sensor_id.clone(),
+                    values: vec![value],
+                }),
+            }
+        }
+        Screen::Sensors(sensors)
+    }
+
+    pub fn history(history: &History) -> Screen {
+        let (temperature, humidity) = history.series();
+        Screen::History { temperature, humidity }
+    }
+
+    pub fn min_max(history: &History, at: SystemTime) -> Screen {
+        Screen::MinMax(history.min_max(at))
+    }
+
+    pub fn status(device_id: &str, ip: Option<IpAddr>, connected: Option<bool>, last_delivery: Option<SystemTime>) -> Screen {
+        Screen::Status(Status {
+            device_id: device_id.to_string(),
+            ip: ip.map(|it| it.to_string()),
+            connected,
+            last_delivery: last_delivery.map(|it| utc_date_time(it).1),
+        })
+    }
+
+    pub fn stopped(at: SystemTime) -> Screen {
+        let (date, time) = utc_date_time(at);
+        Screen::Stopped { date, time }
+    }
+}
+
+//"2024-03-01" and "12:34:56 UTC"
+fn utc_date_time(at: SystemTime) -> (String, String) {
+    let at = humantime::format_rfc3339_seconds(at).to_string();
+    (at[..10].to_string(), format!("{} UTC", &at[11..19]))
+}
+
+/// A panel that can show a `Screen`.
+///
+/// Backends draw with `render::render` into a buffer in the controller's own pixel format and
+/// push it to the panel, so one layout code serves every controller.
+pub trait DisplayBackend: Send {
+    /// Drawing area after rotation.
+    fn size(&self) -> Size;
+
+    fn show(&mut self, screen: &Screen) -> DeviceResult<()>;
+
+    /// Whether `show_partial` updates only the changed pixels, the OLEDs redraw quickly anyway.
+    fn partial_refresh(&self) -> bool {
+        false
+    }
+
+    fn show_partial(&mut self, screen: &Screen) -> DeviceResult<()> {
+        self.show(screen)
+    }
+}
+
+/// Opens the panel selected by `config.driver`, I2C panels get their bus from `i2c`.
+pub fn open(config: &DisplayConfig, i2c: &mut I2cBusManager) -> DeviceResult<Box<dyn DisplayBackend>> {
+    if config.headless {
+        return Ok(Box::new(HeadlessDisplay::new(config)));
+    }
+    match config.driver {
+        DisplayDriver::Ssd1680 => Ok(Box::new(EpaperDisplay::new(config)?)),
+        DisplayDriver::Sh1106 => oled::open_sh1106(config, i2c),
+        DisplayDriver::Ssd1306 => oled::open_ssd1306(config, i2c),
+    }
+}
+
+/// Drawing area of the configured panel after rotation.
+pub fn panel_size(config: &DisplayConfig) -> Size {
+    let (width, height) = match config.driver {
+        //122 wide in its native orientation
+        DisplayDriver::Ssd1680 => (u32::from(ssd1680::WIDTH), u32::from(ssd1680::HEIGHT)),
+        _ => config.oled_size().unwrap_or((128, 64)),
+    };
+    match config.rotation() {
+        90 | 270 => Size::new(height, width),
+        _ => Size::new(width, height),
+    }
+}
+
+/// Renders `screen` for a panel of `size` and writes it to a .png or .pbm file.
+pub fn write_snapshot(screen: &Screen, size: Size, path: &Path) -> DeviceResult<()> {
+    let mut framebuffer = Framebuffer::new(size);
+    render::render(screen, size, &mut framebuffer);
+    framebuffer.save(path)
+}
+
+fn open_spi(config: &DisplayConfig) -> DeviceResult<Spi> {
+    let bus = match config.spi_bus {
+        0 => rppal::spi::Bus::Spi0,
+        1 => rppal::spi::Bus::Spi1,
+        2 => rppal::spi::Bus::Spi2,
+        3 => rppal::spi::Bus::Spi3,
+        4 => rppal::spi::Bus::Spi4,
+        5 => rppal::spi::Bus::Spi5,
+        _ => rppal::spi::Bus::Spi6,
+    };
+    Spi::new(
+        bus,
+        rppal::spi::SlaveSelect::Ss0,
+        config.spi_clock_hz,
+        rppal::spi::Mode::Mode0,
+    )
+    .map_err(|source| DeviceError::Spi {
+        context: format!("open bus {}", config.spi_bus),
+        source,
+    })
+}
+
+fn open_gpio() -> DeviceResult<Gpio> {
+    Gpio::new().map_err(|source| DeviceError::Gpio {
+        context: "open".to_string(),
+        source,
+    })
+}
+
+fn output_pin(gpio: &Gpio, name: &str, number: u8) -> DeviceResult<OutputPin> {
+    gpio.get(number)
+        .map(|pin| pin.into_output())
+        .map_err(|source| DeviceError::Gpio {
+            context: format!("{} pin {}", name, number),
+            source,
+        })
+}
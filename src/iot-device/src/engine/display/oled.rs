@@ -0,0 +1,209 @@
+use std::fmt::Debug;
+
+use embedded_graphics::geometry::Size;
+use rppal::gpio::OutputPin;
+use sh1106::interface::DisplayInterface as Sh1106Interface;
+use sh1106::mode::GraphicsMode;
+use ssd1306::mode::{BufferedGraphicsMode, DisplayConfig as _};
+use ssd1306::prelude::WriteOnlyDataCommand;
+use ssd1306::size::{DisplaySize, DisplaySize128x32, DisplaySize128x64, DisplaySize64x48, DisplaySize72x40, DisplaySize96x16};
+use ssd1306::Ssd1306;
+
+use super::{open_gpio, open_spi, output_pin, panel_size, render, DisplayBackend, Framebuffer, Screen};
+use crate::engine::config::{DisplayConfig, DisplayInterface};
+use crate::engine::i2c_bus::I2cBusManager;
+use crate::error::{DeviceError, DeviceResult};
+
+/// SH1106 OLED, 128x64 modules usually.
+///
+/// The sh1106 crate draws with a newer embedded-graphics, so the screen is rendered into a
+/// `Framebuffer` and copied into the driver buffer.
+pub struct Sh1106Display<DI: Sh1106Interface> {
+    display: GraphicsMode<DI>,
+    size: Size,
+    //dropping the pin would release the reset line
+    _rst: Option<OutputPin>,
+}
+
+impl<DI> DisplayBackend for Sh1106Display<DI>
+where
+    DI: Sh1106Interface + Send,
+    DI::Error: Debug,
+{
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn show(&mut self, screen: &Screen) -> DeviceResult<()> {
+        let mut framebuffer = Framebuffer::new(self.size);
+        render::render(screen, self.size, &mut framebuffer);
+        for (x, y, on) in framebuffer.pixels() {
+            self.display.set_pixel(x, y, on as u8);
+        }
+        self.display
+            .flush()
+            .map_err(|err| DeviceError::Display(format!("SH1106 refresh failed: {:?}", err)))
+    }
+}
+
+/// Opens an SH1106 on I2C, sharing the adapter with the sensors, or on SPI.
+pub fn open_sh1106(config: &DisplayConfig, i2c: &mut I2cBusManager) -> DeviceResult<Box<dyn DisplayBackend>> {
+    let display_size = match config.oled_size() {
+        Some((128, 32)) => sh1106::displaysize::DisplaySize::Display128x32,
+        Some((132, 64)) => sh1106::displaysize::DisplaySize::Display132x64,
+        _ => sh1106::displaysize::DisplaySize::Display128x64,
+    };
+    let rotation = match config.rotation() {
+        90 => sh1106::displayrotation::DisplayRotation::Rotate90,
+        180 => sh1106::displayrotation::DisplayRotation::Rotate180,
+        270 => sh1106::displayrotation::DisplayRotation::Rotate270,
+        _ => sh1106::displayrotation::DisplayRotation::Rotate0,
+    };
+    let builder = sh1106::Builder::new()
+        .with_size(display_size)
+        .with_rotation(rotation);
+
+    match config.interface {
+        DisplayInterface::I2c => {
+            let bus = i2c.device(config.i2c_bus, config.i2c_address, "display")?;
+            let display: GraphicsMode<_> = builder.with_i2c_addr(config.i2c_address).connect_i2c(bus).into();
+            start_sh1106(display, config, None)
+        }
+        DisplayInterface::Spi => {
+            let gpio = open_gpio()?;
+            let spi = open_spi(config)?;
+            let dc = output_pin(&gpio, "dc", config.dc_pin)?;
+            let cs = output_pin(&gpio, "cs", config.cs_pin)?;
+            let mut rst = output_pin(&gpio, "rst", config.rst_pin)?;
+
+            let mut display: GraphicsMode<_> = builder.connect_spi(spi, dc, cs).into();
+            display
+                .reset(&mut rst, &mut rppal::hal::Delay)
+                .map_err(|err| DeviceError::Display(format!("SH1106 reset failed: {:?}", err)))?;
+            start_sh1106(display, config, Some(rst))
+        }
+    }
+}
+
+fn start_sh1106<DI>(
+    mut display: GraphicsMode<DI>,
+    config: &DisplayConfig,
+    rst: Option<OutputPin>,
+) -> DeviceResult<Box<dyn DisplayBackend>>
+where
+    DI: Sh1106Interface + Send + 'static,
+    DI::Error: Debug,
+{
+    display
+        .init()
+        .map_err(|err| DeviceError::Display(format!("SH1106 init failed: {:?}", err)))?;
+    Ok(Box::new(Sh1106Display {
+        display,
+        size: panel_size(config),
+        _rst: rst,
+    }))
+}
+
+/// SSD1306 OLED, from 64x48 to 128x64.
+///
+/// Rendered through a `Framebuffer` like the SH1106, the ssd1306 crate uses a newer embedded-graphics too.
+pub struct Ssd1306Display<DI, SIZE: DisplaySize> {
+    display: Ssd1306<DI, SIZE, BufferedGraphicsMode<SIZE>>,
+    size: Size,
+    //dropping the pin would release the reset line
+    _rst: Option<OutputPin>,
+}
+
+impl<DI, SIZE> DisplayBackend for Ssd1306Display<DI, SIZE>
+where
+    DI: WriteOnlyDataCommand + Send,
+    SIZE: DisplaySize + Send,
+    <SIZE as DisplaySize>::Buffer: Send,
+{
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn show(&mut self, screen: &Screen) -> DeviceResult<()> {
+        let mut framebuffer = Framebuffer::new(self.size);
+        render::render(screen, self.size, &mut framebuffer);
+        for (x, y, on) in framebuffer.pixels() {
+            self.display.set_pixel(x, y, on);
+        }
+        self.display
+            .flush()
+            .map_err(|err| DeviceError::Display(format!("SSD1306 refresh failed: {:?}", err)))
+    }
+}
+
+/// Opens an SSD1306 on I2C, sharing the adapter with the sensors, or on SPI.
+pub fn open_ssd1306(config: &DisplayConfig, i2c: &mut I2cBusManager) -> DeviceResult<Box<dyn DisplayBackend>> {
+    match config.interface {
+        DisplayInterface::I2c => {
+            let bus = i2c.device(config.i2c_bus, config.i2c_address, "display")?;
+            let interface = ssd1306::I2CDisplayInterface::new_custom_address(bus, config.i2c_address);
+            ssd1306_with_size(interface, config, None)
+        }
+        DisplayInterface::Spi => {
+            let gpio = open_gpio()?;
+            let spi = open_spi(config)?;
+            let dc = output_pin(&gpio, "dc", config.dc_pin)?;
+            let cs = output_pin(&gpio, "cs", config.cs_pin)?;
+            let rst = output_pin(&gpio, "rst", config.rst_pin)?;
+            let interface = ssd1306::prelude::SPIInterface::new(spi, dc, cs);
+            ssd1306_with_size(interface, config, Some(rst))
+        }
+    }
+}
+
+//the panel size is a type parameter of the driver
+fn ssd1306_with_size<DI>(
+    interface: DI,
+    config: &DisplayConfig,
+    rst: Option<OutputPin>,
+) -> DeviceResult<Box<dyn DisplayBackend>>
+where
+    DI: WriteOnlyDataCommand + Send + 'static,
+{
+    match config.oled_size() {
+        Some((128, 32)) => start_ssd1306(interface, DisplaySize128x32, config, rst),
+        Some((96, 16)) => start_ssd1306(interface, DisplaySize96x16, config, rst),
+        Some((72, 40)) => start_ssd1306(interface, DisplaySize72x40, config, rst),
+        Some((64, 48)) => start_ssd1306(interface, DisplaySize64x48, config, rst),
+        _ => start_ssd1306(interface, DisplaySize128x64, config, rst),
+    }
+}
+
+fn start_ssd1306<DI, SIZE>(
+    interface: DI,
+    display_size: SIZE,
+    config: &DisplayConfig,
+    mut rst: Option<OutputPin>,
+) -> DeviceResult<Box<dyn DisplayBackend>>
+where
+    DI: WriteOnlyDataCommand + Send + 'static,
+    SIZE: DisplaySize + Send + 'static,
+    <SIZE as DisplaySize>::Buffer: Send,
+{
+    let rotation = match config.rotation() {
+        90 => ssd1306::rotation::DisplayRotation::Rotate90,
+        180 => ssd1306::rotation::DisplayRotation::Rotate180,
+        270 => ssd1306::rotation::DisplayRotation::Rotate270,
+        _ => ssd1306::rotation::DisplayRotation::Rotate0,
+    };
+    let mut display = Ssd1306::new(interface, display_size, rotation).into_buffered_graphics_mode();
+    if let Some(rst) = rst.as_mut() {
+        display
+            .reset(rst, &mut rppal::hal::Delay)
+            .map_err(|err| DeviceError::Display(format!("SSD1306 reset failed: {:?}", err)))?;
+    }
+    display
+        .init()
+        .map_err(|err| DeviceError::Display(format!("SSD1306 init failed: {:?}", err)))?;
+
+    Ok(Box::new(Ssd1306Display {
+        display,
+        size: panel_size(config),
+        _rst: rst,
+    }))
+}
@@ -0,0 +1,358 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use embedded_graphics::geometry::Size;
+
+use super::{DisplayBackend, Screen};
+use crate::engine::config::RefreshConfig;
+use crate::engine::health::RefreshCounters;
+use crate::engine::history::Range;
+use crate::engine::sensors::Quantity;
+use crate::error::DeviceResult;
+
+/// Wraps a backend and decides for every refresh whether to skip it, update partially or redraw fully.
+///
+/// A screen is skipped while it shows the same page as the last one and every value stayed within its
+/// deadband, so slow drifts still show once they add up. A partial update is used when the backend
+/// has one, the page did not change and fewer than `max_partial_refreshes` partials ran since the
+/// last full refresh. Once `max_full_interval_secs` passed since the last full refresh the next one
+/// is full, also when nothing moved beyond the deadbands.
+pub struct RefreshPolicy {
+    inner: Box<dyn DisplayBackend>,
+    config: RefreshConfig,
+    //what the panel shows, None after a failed refresh so the next one is full
+    shown: Option<Screen>,
+    partials_in_a_row: u32,
+    last_full: Option<Instant>,
+    counters: Arc<Mutex<RefreshCounters>>,
+}
+
+impl RefreshPolicy {
+    pub fn new(inner: Box<dyn DisplayBackend>, config: RefreshConfig) -> RefreshPolicy {
+        RefreshPolicy {
+            inner,
+            config,
+            shown: None,
+            partials_in_a_row: 0,
+            last_full: None,
+            counters: Arc::new(Mutex::new(RefreshCounters::default())),
+        }
+    }
+
+    /// Counters shared with the health report.
+    pub fn counters(&self) -> Arc<Mutex<RefreshCounters>> {
+        self.counters.clone()
+    }
+
+    fn full_due(&self) -> bool {
+        let interval = Duration::from_secs(self.config.max_full_interval_secs);
+        !interval.is_zero() && self.last_full.is_some_and(|at| at.elapsed() >= interval)
+    }
+
+    fn count(&self, update: impl FnOnce(&mut RefreshCounters)) {
+        update(&mut self.counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+}
+
+impl DisplayBackend for RefreshPolicy {
+    fn size(&self) -> Size {
+        self.inner.size()
+    }
+
+    fn show(&mut self, screen: &Screen) -> DeviceResult<()> {
+        //same page and no full refresh due, the only case where skipping or a partial update is allowed
+        let incremental = self.shown.as_ref().is_some_and(|shown| same_page(shown, screen)) && !self.full_due();
+        if incremental && self.shown.as_ref().is_some_and(|shown| within_deadband(shown, screen, &self.config)) {
+            self.count(|it| it.skipped += 1);
+            return Ok(());
+        }
+
+        let partial = incremental
+            && self.inner.partial_refresh()
+            && self.partials_in_a_row < self.config.max_partial_refreshes;
+        let result = if partial {
+            self.inner.show_partial(screen)
+        } else {
+            self.inner.show(screen)
+        };
+
+        match result {
+            Ok(()) => {
+                self.shown = Some(screen.clone());
+                if partial {
+                    self.partials_in_a_row += 1;
+                    self.count(|it| it.partial += 1);
+                } else {
+                    self.partials_in_a_row = 0;
+                    self.last_full = Some(Instant::now());
+                    self.count(|it| it.full += 1);
+                }
+                Ok(())
+            }
+            Err(err) => {
+                self.shown = None;
+                self.count(|it| it.failed += 1);
+                Err(err)
+            }
+        }
+    }
+}
+
+fn same_page(a: &Screen, b: &Screen) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+//true when `next` differs from `shown` by no more than the deadbands, texts and the demo switch have to be equal
+fn within_deadband(shown: &Screen, next: &Screen, config: &RefreshConfig) -> bool {
+    let near = |quantity: Quantity, a: Option<f32>, b: Option<f32>| {
+        let deadband = match quantity {
+            Quantity::Temperature => config.temperature_deadband,
+            Quantity::Humidity => config.humidity_deadband,
+            Quantity::Pressure => config.pressure_deadband,
+        };
+        match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() <= deadband,
+            (None, None) => true,
+            _ => false,
+        }
+    };
+    let near_range = |quantity: Quantity, a: Option<Range>, b: Option<Range>| match (a, b) {
+        (Some(a), Some(b)) => near(quantity, Some(a.min), Some(b.min)) && near(quantity, Some(a.max), Some(b.max)),
+        (a, b) => a.is_none() && b.is_none(),
+    };
+    let near_all = |quantity: Quantity, a: &[Option<f32>], b: &[Option<f32>]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| near(quantity, *a, *b))
+    };
+
+    match (shown, next) {
+        (Screen::Values(a), Screen::Values(b)) => {
+            a.demo_switch == b.demo_switch
+                && near(Quantity::Temperature, a.temperature, b.temperature)
+                && near(Quantity::Humidity, a.humidity, b.humidity)
+                && near(Quantity::Pressure, a.pressure, b.pressure)
+                && a.probes.len() == b.probes.len()
+                && a.probes.iter().zip(&b.probes).all(|((serial_a, a), (serial_b, b))| {
+                    serial_a == serial_b && near(Quantity::Temperature, *a, *b)
+                })
+        }
+        (Screen::Sensors(a), Screen::Sensors(b)) => {
+            a.len() == b.len()
+                && a.iter().zip(b).all(|(a, b)| {
+                    a.sensor_id == b.sensor_id
+                        && a.values.len() == b.values.len()
+                        && a.values.iter().zip(&b.values).all(|((quantity_a, a), (quantity_b, b))| {
+                            quantity_a == quantity_b && near(*quantity_a, *a, *b)
+                        })
+                })
+        }
+        (
+            Screen::History { temperature, humidity },
+            Screen::History {
+                temperature: next_temperature,
+                humidity: next_humidity,
+            },
+        ) => {
+            near_all(Quantity::Temperature, temperature, next_temperature)
+                && near_all(Quantity::Humidity, humidity, next_humidity)
+        }
+        (Screen::MinMax(a), Screen::MinMax(b)) => {
+            near_range(Quantity::Temperature, a.temperature, b.temperature)
+                && near_range(Quantity::Humidity, a.humidity, b.humidity)
+                && near_range(Quantity::Pressure, a.pressure, b.pressure)
+        }
+        (shown, next) => shown == next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::display::Values;
+    use crate::error::DeviceError;
+
+    //records "full" or "partial" for every refresh that reaches the panel
+    struct FakePanel {
+        partial_refresh: bool,
+        fail: Arc<Mutex<bool>>,
+        refreshes: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl FakePanel {
+        fn refresh(&self, kind: &'static str) -> DeviceResult<()> {
+            if *self.fail.lock().unwrap() {
+                return Err(DeviceError::Display("busy".to_string()));
+            }
+            self.refreshes.lock().unwrap().push(kind);
+            Ok(())
+        }
+    }
+
+    impl DisplayBackend for FakePanel {
+        fn size(&self) -> Size {
+            Size::new(250, 122)
+        }
+
+        fn show(&mut self, _screen: &Screen) -> DeviceResult<()> {
+            self.refresh("full")
+        }
+
+        fn partial_refresh(&self) -> bool {
+            self.partial_refresh
+        }
+
+        fn show_partial(&mut self, _screen: &Screen) -> DeviceResult<()> {
+            self.refresh("partial")
+        }
+    }
+
+    struct Harness {
+        policy: RefreshPolicy,
+        fail: Arc<Mutex<bool>>,
+        refreshes: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Harness {
+        fn new(partial_refresh: bool, config: RefreshConfig) -> Harness {
+            let fail = Arc::new(Mutex::new(false));
+            let refreshes = Arc::new(Mutex::new(Vec::new()));
+            let panel = FakePanel {
+                partial_refresh,
+                fail: fail.clone(),
+                refreshes: refreshes.clone(),
+            };
+            Harness {
+                policy: RefreshPolicy::new(Box::new(panel), config),
+                fail,
+                refreshes,
+            }
+        }
+
+        //the refreshes since the last call
+        fn take(&self) -> Vec<&'static str> {
+            std::mem::take(&mut self.refreshes.lock().unwrap())
+        }
+    }
+
+    fn values(temperature: f32) -> Screen {
+        Screen::Values(Values {
+            temperature: Some(temperature),
+            humidity: Some(45.0),
+            pressure: Some(101.3),
+            demo_switch: false,
+            probes: Vec::new(),
+        })
+    }
+
+    fn stopped() -> Screen {
+        Screen::Stopped {
+            date: "2024-03-01".to_string(),
+            time: "12:00:00 UTC".to_string(),
+        }
+    }
+
+    fn config() -> RefreshConfig {
+        RefreshConfig {
+            temperature_deadband: 0.5,
+            max_partial_refreshes: 2,
+            ..RefreshConfig::default()
+        }
+    }
+
+    #[test]
+    fn skips_changes_within_the_deadband() {
+        let mut harness = Harness::new(true, config());
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&values(20.3)).unwrap();
+        //exactly the deadband away from what the panel shows
+        harness.policy.show(&values(20.5)).unwrap();
+        assert_eq!(harness.take(), ["full"]);
+
+        harness.policy.show(&values(20.6)).unwrap();
+        assert_eq!(harness.take(), ["partial"]);
+
+        let counters = harness.policy.counters();
+        let counters = counters.lock().unwrap();
+        assert_eq!((counters.full, counters.partial, counters.skipped), (1, 1, 2));
+    }
+
+    #[test]
+    fn zero_deadband_skips_only_identical_values() {
+        let mut harness = Harness::new(true, RefreshConfig {
+            temperature_deadband: 0.0,
+            ..config()
+        });
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&values(20.01)).unwrap();
+        assert_eq!(harness.take(), ["full", "partial"]);
+    }
+
+    #[test]
+    fn a_page_change_refreshes_fully() {
+        let mut harness = Harness::new(true, config());
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&stopped()).unwrap();
+        harness.policy.show(&stopped()).unwrap();
+        harness.policy.show(&values(20.0)).unwrap();
+        assert_eq!(harness.take(), ["full", "full", "full"]);
+    }
+
+    #[test]
+    fn partials_in_a_row_are_capped() {
+        let mut harness = Harness::new(true, config());
+        for temperature in [20.0, 21.0, 22.0, 23.0, 24.0] {
+            harness.policy.show(&values(temperature)).unwrap();
+        }
+        assert_eq!(harness.take(), ["full", "partial", "partial", "full", "partial"]);
+    }
+
+    #[test]
+    fn backends_without_partial_refresh_always_refresh_fully() {
+        let mut harness = Harness::new(false, config());
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&values(21.0)).unwrap();
+        assert_eq!(harness.take(), ["full", "full"]);
+    }
+
+    #[test]
+    fn a_failed_refresh_makes_the_next_one_full() {
+        let mut harness = Harness::new(true, config());
+        harness.policy.show(&values(20.0)).unwrap();
+        *harness.fail.lock().unwrap() = true;
+        assert!(harness.policy.show(&values(21.0)).is_err());
+        *harness.fail.lock().unwrap() = false;
+        //within the deadband of the failed screen, but the panel state is unknown
+        harness.policy.show(&values(21.0)).unwrap();
+        assert_eq!(harness.take(), ["full", "full"]);
+        assert_eq!(harness.policy.counters().lock().unwrap().failed, 1);
+    }
+
+    #[test]
+    fn a_full_refresh_is_forced_after_the_max_interval() {
+        let mut harness = Harness::new(true, RefreshConfig {
+            max_full_interval_secs: 60,
+            ..config()
+        });
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.show(&values(21.0)).unwrap();
+        assert_eq!(harness.take(), ["full", "partial"]);
+
+        harness.policy.last_full = Instant::now().checked_sub(Duration::from_secs(61));
+        harness.policy.show(&values(21.0)).unwrap();
+        harness.policy.show(&values(21.0)).unwrap();
+        assert_eq!(harness.take(), ["full"]);
+    }
+
+    #[test]
+    fn zero_max_interval_never_forces_a_full_refresh() {
+        let mut harness = Harness::new(true, RefreshConfig {
+            max_full_interval_secs: 0,
+            ..config()
+        });
+        harness.policy.show(&values(20.0)).unwrap();
+        harness.policy.last_full = Instant::now().checked_sub(Duration::from_secs(24 * 60 * 60));
+        harness.policy.show(&values(20.0)).unwrap();
+        assert_eq!(harness.take(), ["full"]);
+    }
+}
@@ -0,0 +1,451 @@
+use embedded_graphics::egcircle;
+use embedded_graphics::egline;
+use embedded_graphics::fonts::*;
+use embedded_graphics::pixelcolor::*;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitive_style;
+
+use super::{SensorValues, Screen, Status, Values};
+use crate::engine::history::{MinMax, Range, HISTORY_POINTS};
+use crate::engine::sensors::{ds18b20, Quantity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontSize {
+    //Font6x8
+    Small,
+    //Font12x16
+    Large,
+}
+
+impl FontSize {
+    pub fn char_size(self) -> Size {
+        match self {
+            FontSize::Small => Size::new(6, 8),
+            FontSize::Large => Size::new(12, 16),
+        }
+    }
+}
+
+/// One thing drawn on a screen, `elements` lists them so the layout can be checked without pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Text {
+        text: String,
+        font: FontSize,
+        position: Point,
+    },
+    //the demo switch indicator, filled when the switch is on
+    Circle {
+        center: Point,
+        radius: u32,
+        stroke: u32,
+        filled: bool,
+    },
+    //a segment of a sparkline, a lone point has the same start and end
+    Line {
+        start: Point,
+        end: Point,
+    },
+}
+
+impl Element {
+    /// Top left corner and bottom right corner, both inclusive.
+    pub fn bounds(&self) -> (Point, Point) {
+        match self {
+            Element::Text { text, font, position } => {
+                let char_size = font.char_size();
+                let width = (text.chars().count() as u32 * char_size.width) as i32;
+                let bottom_right = *position + Point::new(width - 1, char_size.height as i32 - 1);
+                (*position, bottom_right)
+            }
+            Element::Circle { center, radius, .. } => {
+                let radius = *radius as i32;
+                (*center - Point::new(radius, radius), *center + Point::new(radius, radius))
+            }
+            Element::Line { start, end } => (
+                Point::new(start.x.min(end.x), start.y.min(end.y)),
+                Point::new(start.x.max(end.x), start.y.max(end.y)),
+            ),
+        }
+    }
+}
+
+/// Where the elements of a screen go on a panel of a given size.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    width: u32,
+    height: u32,
+    font: FontSize,
+    row_height: i32,
+    //temperature, humidity and pressure, the shortest OLEDs leave the pressure out
+    value_rows: usize,
+    //"Temperature: 21.50 C" instead of "21.50 C", dropped for a row that would not fit
+    labels: bool,
+    circle_center: Point,
+    circle_radius: u32,
+    circle_stroke: u32,
+    probe_first_row: i32,
+    probe_rows: usize,
+}
+
+const PROBE_ROW_HEIGHT: i32 = 10;
+
+impl Layout {
+    fn for_size(size: Size) -> Layout {
+        if size.width >= 200 && size.height >= 100 {
+            //tuned by hand on the 250x122 e-paper, probes go in Font6x8 rows left of the circle
+            return Layout {
+                width: size.width,
+                height: size.height,
+                font: FontSize::Large,
+                row_height: 17,
+                value_rows: 3,
+                labels: true,
+                circle_center: Point::new(125, 90),
+                circle_radius: 20,
+                circle_stroke: 2,
+                probe_first_row: 52,
+                probe_rows: 7,
+            };
+        }
+
+        //OLEDs from 64x48 to 132x64: three 6x8 rows of values, probes below, the circle in the bottom right corner,
+        //a probe row is 16 characters and does not fit next to the circle on the narrow panels
+        let first_row = 32;
+        let probe_rows = if size.width >= 96 {
+            (size.height as i32 - first_row).max(0) / PROBE_ROW_HEIGHT
+        } else {
+            0
+        };
+        //96x16 only has room for two packed rows
+        let (row_height, value_rows) = if size.height < 30 { (8, 2) } else { (10, 3) };
+        Layout {
+            width: size.width,
+            height: size.height,
+            font: FontSize::Small,
+            row_height,
+            value_rows,
+            labels: false,
+            circle_center: Point::new(size.width as i32 - 7, size.height as i32 - 7),
+            circle_radius: 5,
+            circle_stroke: 1,
+            probe_first_row: first_row,
+            probe_rows: probe_rows as usize,
+        }
+    }
+
+    fn fits(&self, text: &str, font: FontSize) -> bool {
+        text.chars().count() as u32 * font.char_size().width <= self.width
+    }
+
+    //the first text that fits the width, the last one cut at the edge otherwise
+    fn fit<S: AsRef<str>>(&self, texts: &[S], font: FontSize) -> String {
+        match texts.iter().find(|it| self.fits(it.as_ref(), font)) {
+            Some(text) => text.as_ref().to_string(),
+            None => {
+                let chars = (self.width / font.char_size().width) as usize;
+                texts.last().map(|it| it.as_ref().chars().take(chars).collect()).unwrap_or_default()
+            }
+        }
+    }
+
+    //rows of Font6x8, packed on the shortest OLEDs
+    fn small_row_height(&self) -> i32 {
+        match self.font {
+            FontSize::Small => self.row_height,
+            FontSize::Large => PROBE_ROW_HEIGHT,
+        }
+    }
+
+    //rows of `row_height` whose text is completely on the panel
+    fn rows(&self, font: FontSize, row_height: i32) -> usize {
+        let free = self.height as i32 - font.char_size().height as i32;
+        if free < 0 {
+            0
+        } else {
+            (free / row_height + 1) as usize
+        }
+    }
+}
+
+/// Draws `screen` for a panel of `size` (after rotation).
+///
+/// `BinaryColor::On` is ink on the e-paper and a lit pixel on the OLEDs, drivers map it to their
+/// own pixel format. `size` is passed in because not every driver reports its rotated size.
+pub fn render<D: DrawTarget<BinaryColor>>(screen: &Screen, size: Size, target: &mut D) {
+    for element in elements(screen, size) {
+        draw(&element, target);
+    }
+}
+
+/// What `render` draws for `screen` on a panel of `size`, in drawing order.
+pub fn elements(screen: &Screen, size: Size) -> Vec<Element> {
+    let layout = Layout::for_size(size);
+    let mut elements = Vec::new();
+    match screen {
+        Screen::Values(values) => values_elements(values, &layout, &mut elements),
+        Screen::Sensors(sensors) => sensors_elements(sensors, &layout, &mut elements),
+        Screen::History { temperature, humidity } => history_elements(temperature, humidity, &layout, &mut elements),
+        Screen::MinMax(min_max) => min_max_elements(min_max, &layout, &mut elements),
+        Screen::Status(status) => status_elements(status, &layout, &mut elements),
+        Screen::Stopped { date, time } => {
+            let rows = match (layout.font, layout.row_height) {
+                (FontSize::Large, _) => [0, 34, 51],
+                (FontSize::Small, 8) => [0, 8, 16],
+                (FontSize::Small, _) => [0, 12, 22],
+            };
+            let texts = [
+                layout.fit(&["Device stopped", "Stopped"], layout.font),
+                layout.fit(&[date.as_str()], layout.font),
+                layout.fit(&[time.as_str(), time.trim_end_matches(" UTC")], layout.font),
+            ];
+            //the short OLEDs leave the last rows out
+            let char_height = layout.font.char_size().height as i32;
+            for (text, y) in texts.iter().zip(rows).filter(|(_, y)| y + char_height <= layout.height as i32) {
+                elements.push(text_element(text, layout.font, 0, y));
+            }
+        }
+    }
+    elements
+}
+
+fn values_elements(values: &Values, layout: &Layout, elements: &mut Vec<Element>) {
+    let rows = [
+        ("Temperature: ", values.temperature, " C"),
+        ("Humidity: ", values.humidity, " %"),
+        ("Pressure: ", values.pressure, " hPa"),
+    ];
+    for (index, (label, value, unit)) in rows.iter().take(layout.value_rows).enumerate() {
+        let value = format!("{}{}", format_value(*value), unit);
+        let labelled = format!("{}{}", label, value);
+        //negative temperatures and long pressures do not fit the e-paper with the label
+        let text = if layout.labels && layout.fits(&labelled, layout.font) { labelled } else { value };
+        elements.push(text_element(&text, layout.font, 0, index as i32 * layout.row_height));
+    }
+
+    probe_elements(values, layout, elements);
+
+    elements.push(Element::Circle {
+        center: layout.circle_center,
+        radius: layout.circle_radius,
+        stroke: layout.circle_stroke,
+        filled: values.demo_switch,
+    });
+}
+
+//one row per probe keyed by the end of its serial, "2795aff 21.31 C"
+fn probe_elements(values: &Values, layout: &Layout, elements: &mut Vec<Element>) {
+    let probes = &values.probes;
+    for (row, (serial, value)) in probes.iter().take(layout.probe_rows).enumerate() {
+        let short = &serial[serial.len().saturating_sub(7)..];
+        let text = if row == layout.probe_rows - 1 && probes.len() > layout.probe_rows {
+            format!("+{} more", probes.len() - row)
+        } else {
+            format!("{} {} C", short, format_value(*value))
+        };
+        let y = layout.probe_first_row + row as i32 * PROBE_ROW_HEIGHT;
+        elements.push(text_element(&text, FontSize::Small, 0, y));
+    }
+}
+
+//one row per sensor, "aht20 21.5C 45.3%"
+fn sensors_elements(sensors: &[SensorValues], layout: &Layout, elements: &mut Vec<Element>) {
+    let row_height = layout.small_row_height();
+    let rows = layout.rows(FontSize::Small, row_height);
+    for (row, sensor) in sensors.iter().take(rows).enumerate() {
+        let text = if row == rows - 1 && sensors.len() > rows {
+            format!("+{} more", sensors.len() - row)
+        } else {
+            //probe serials are keyed by their end like on the values page
+            let id = if ds18b20::is_probe(&sensor.sensor_id) {
+                &sensor.sensor_id[sensor.sensor_id.len().saturating_sub(7)..]
+            } else {
+                &sensor.sensor_id
+            };
+            let values: Vec<String> = sensor
+                .values
+                .iter()
+                .map(|(quantity, value)| format!("{}{}", format_short(*quantity, *value), quantity.unit()))
+                .collect();
+            format!("{} {}", id, values.join(" "))
+        };
+        elements.push(text_element(&layout.fit(&[text], FontSize::Small), FontSize::Small, 0, row as i32 * row_height));
+    }
+}
+
+//a label with the range of the line and a sparkline below it per quantity, the short OLEDs only get the temperature
+fn history_elements(temperature: &[Option<f32>], humidity: &[Option<f32>], layout: &Layout, elements: &mut Vec<Element>) {
+    let mut sections = vec![("T", Quantity::Temperature, temperature)];
+    if layout.height >= 48 {
+        sections.push(("H", Quantity::Humidity, humidity));
+    }
+    let section_height = layout.height as i32 / sections.len() as i32;
+    for (index, (label, quantity, points)) in sections.iter().enumerate() {
+        let top = index as i32 * section_height;
+        let range = points.iter().flatten().fold(None, |range, value| Range::extend(range, Some(*value)));
+        let texts = match range {
+            Some(range) => {
+                let range = format!("{} {}..{}", label, format_short(*quantity, Some(range.min)), format_short(*quantity, Some(range.max)));
+                vec![format!("{} {} 24h", range, quantity.unit()), format!("{} {}", range, quantity.unit()), range]
+            }
+            None => vec![format!("{} -- 24h", label), format!("{} --", label)],
+        };
+        elements.push(text_element(&layout.fit(&texts, FontSize::Small), FontSize::Small, 0, top));
+
+        //label, a gap and the line, too short to read below 4 pixels
+        let chart_height = section_height - 12;
+        if let (Some(range), true) = (range, chart_height >= 4) {
+            sparkline(points, range, top + 10, chart_height, layout, elements);
+        }
+    }
+}
+
+//right aligned on a fixed 24 h axis, so a history that just started stays at the right edge; gaps break the line
+fn sparkline(points: &[Option<f32>], range: Range, top: i32, height: i32, layout: &Layout, elements: &mut Vec<Element>) {
+    let right = layout.width as i32 - 1;
+    let step = right as f32 / (HISTORY_POINTS - 1) as f32;
+    let span = range.max - range.min;
+    let position = |index: usize, value: f32| {
+        let x = right - ((points.len() - 1 - index) as f32 * step).round() as i32;
+        let y = if span > 0.0 {
+            top + height - 1 - ((value - range.min) / span * (height - 1) as f32).round() as i32
+        } else {
+            top + (height - 1) / 2
+        };
+        Point::new(x, y)
+    };
+
+    let mut previous = None;
+    for (index, value) in points.iter().enumerate() {
+        previous = value.map(|value| {
+            let end = position(index, value);
+            elements.push(Element::Line {
+                start: previous.unwrap_or(end),
+                end,
+            });
+            end
+        });
+    }
+}
+
+fn min_max_elements(min_max: &MinMax, layout: &Layout, elements: &mut Vec<Element>) {
+    let row = |label: &str, quantity: Quantity, range: Option<Range>| match range {
+        Some(range) => {
+            let range = format!("{} {}..{}", label, format_short(quantity, Some(range.min)), format_short(quantity, Some(range.max)));
+            vec![format!("{} {}", range, quantity.unit()), range]
+        }
+        None => vec![format!("{} --", label)],
+    };
+    let rows = [
+        vec!["Min..max today".to_string(), "Today".to_string()],
+        row("T", Quantity::Temperature, min_max.temperature),
+        row("H", Quantity::Humidity, min_max.humidity),
+        row("P", Quantity::Pressure, min_max.pressure),
+    ];
+    rows_elements(&rows, layout.font, layout.row_height, layout, elements);
+}
+
+fn status_elements(status: &Status, layout: &Layout, elements: &mut Vec<Element>) {
+    let ip = status.ip.as_deref().unwrap_or("--");
+    let connection = match status.connected {
+        Some(true) => "connected",
+        Some(false) => "offline",
+        None => "--",
+    };
+    let rows = [
+        vec![format!("Device {}", status.device_id), status.device_id.clone()],
+        vec![format!("IP {}", ip), ip.to_string()],
+        vec![format!("MQTT {}", connection), connection.to_string()],
+        match &status.last_delivery {
+            Some(time) => vec![
+                format!("Sent {}", time),
+                format!("Sent {}", time.trim_end_matches(" UTC")),
+                time.trim_end_matches(" UTC").to_string(),
+            ],
+            None => vec!["Sent never".to_string(), "never".to_string()],
+        },
+    ];
+    rows_elements(&rows, layout.font, layout.row_height, layout, elements);
+}
+
+//one row per line from the top with the first alternative that fits, rows below the panel are left out
+fn rows_elements(rows: &[Vec<String>], font: FontSize, row_height: i32, layout: &Layout, elements: &mut Vec<Element>) {
+    for (index, texts) in rows.iter().take(layout.rows(font, row_height)).enumerate() {
+        elements.push(text_element(&layout.fit(texts, font), font, 0, index as i32 * row_height));
+    }
+}
+
+//one decimal, humidity in whole percent
+fn format_short(quantity: Quantity, value: Option<f32>) -> String {
+    match (quantity, value) {
+        (Quantity::Humidity, Some(value)) => format!("{:.0}", value),
+        (_, Some(value)) => format!("{:.1}", value),
+        (_, None) => "--".to_string(),
+    }
+}
+
+fn format_value(value: Option<f32>) -> String {
+    match value {
+        Some(value) => format!("{:.2}", value),
+        None => "--".to_string(),
+    }
+}
+
+fn text_element(text: &str, font: FontSize, x: i32, y: i32) -> Element {
+    Element::Text {
+        text: text.to_string(),
+        font,
+        position: Point::new(x, y),
+    }
+}
+
+fn draw<D: DrawTarget<BinaryColor>>(element: &Element, target: &mut D) {
+    match element {
+        Element::Text { text, font, position } => draw_text(target, *font, text, *position),
+        Element::Circle {
+            center,
+            radius,
+            stroke,
+            filled,
+        } => {
+            let style_demo = if *filled {
+                primitive_style!(stroke_color = BinaryColor::On, fill_color = BinaryColor::On, stroke_width = *stroke)
+            } else {
+                primitive_style!(stroke_color = BinaryColor::On, fill_color = BinaryColor::Off, stroke_width = *stroke)
+            };
+
+            let _ = egcircle!(
+                center = *center,
+                radius = *radius,
+                style = style_demo,
+            ).draw(target);
+        }
+        Element::Line { start, end } => {
+            let _ = egline!(
+                start = *start,
+                end = *end,
+                style = primitive_style!(stroke_color = BinaryColor::On, stroke_width = 1),
+            ).draw(target);
+        }
+    }
+}
+
+fn draw_text<D: DrawTarget<BinaryColor>>(target: &mut D, font: FontSize, text: &str, position: Point) {
+    let text = Text::new(text, position);
+    let _ = match font {
+        FontSize::Small => text
+            .into_styled(embedded_graphics::text_style!(
+                font = Font6x8,
+                text_color = BinaryColor::On,
+                background_color = BinaryColor::Off
+            ))
+            .draw(target),
+        FontSize::Large => text
+            .into_styled(embedded_graphics::text_style!(
+                font = Font12x16,
+                text_color = BinaryColor::On,
+                background_color = BinaryColor::Off
+            ))
+            .draw(target),
+    };
+}
@@ -1,174 +1,392 @@
-use std::{borrow::BorrowMut, error::Error, time::Duration};
-
-use bmp280::Bmp280;
-use rppal::{gpio::Gpio, hal::Delay, i2c::I2c};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use super::{
+    config::{DeviceConfig, DisplayPage},
+    display::{self, DisplayBackend, RefreshPolicy, Screen},
+    hardware::HardwareWorker,
+    health::{self, ReadCounters, RefreshCounters, SystemStats},
+    history::History,
     net_connector::{NetConnector, NetConnectorSettings},
-    spidisplay::{self, SpiDisplay},
-    EnterTimerGuard, ProgramArgs, ResultTable,
+    recorder::Recorder,
+    sensors::{Quantity, Reading, Sensor},
+    ResultTable,
+};
+use tokio::{
+    signal::unix::{signal, Signal, SignalKind},
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::{Interval, MissedTickBehavior},
 };
 
+use crate::error::{DeviceError, DeviceResult};
+use crate::proto::proto_broker_msgs::{server_message::Cmd, ServerMessage};
+
+//result of one read, sent by the sensor tasks to the engine
+struct SensorEvent {
+    sensor_id: String,
+    result: DeviceResult<Vec<Reading>>,
+}
+
 pub struct Engine {
-    args: ProgramArgs,
-    dht22_fs_temp: String,
-    dht22_fs_humidity: String,
+    config: DeviceConfig,
     net_connector: Option<NetConnector>,
-    display: SpiDisplay,
-    aht20: embedded_aht20::Aht20<I2c, Delay>,
-    bmp280: Bmp280,
+    //refreshes run on the worker thread, the loop does not wait for them
+    display: Option<HardwareWorker<Box<dyn DisplayBackend>>>,
+    //kept by the refresh policy on the display thread, read for the health report
+    display_refreshes: Option<Arc<Mutex<RefreshCounters>>>,
+    //moved into their sampling tasks by run
+    sensors: Vec<Box<dyn Sensor>>,
+    read_counters: Vec<(String, ReadCounters)>,
+    recorder: Option<Recorder>,
     result_table: ResultTable,
+    //air averages for the history and min/max pages
+    history: History,
+    //index into config.display.pages
+    page: usize,
+    started: Instant,
+    //server commands, the sender is handed to every net connector
+    command_tx: mpsc::Sender<ServerMessage>,
+    command_rx: Option<mpsc::Receiver<ServerMessage>>,
 }
 
 impl Engine {
-    pub fn new(args: ProgramArgs) -> Engine {
-        let dht22_fs_temp = "/sys/bus/iio/devices/iio:device0/in_temp_input".to_string();
-        let dht22_fs_humidity =
-            "/sys/bus/iio/devices/iio:device0/in_humidityrelative_input".to_string();
+    pub fn new(
+        config: DeviceConfig,
+        sensors: Vec<Box<dyn Sensor>>,
+        display: Option<Box<dyn DisplayBackend>>,
+    ) -> Engine {
         let net_connector = None;
-        let result_table = ResultTable::default();
-
-        let i2c = rppal::i2c::I2c::new().unwrap();
-        let aht20 =
-            embedded_aht20::Aht20::new(i2c, embedded_aht20::DEFAULT_I2C_ADDRESS, rppal::hal::Delay)
-                .unwrap();
-        let bmp280 = bmp280::Bmp280Builder::new()
-            .address(0x77) // Optional
-            .path("/dev/i2c-1") // Optional
-            .build()
-            .expect("Could not build device");
-
-        let display = SpiDisplay::new();
+        let mut result_table = ResultTable {
+            stale_after: Duration::from_secs(config.intervals.stale_secs),
+            ..ResultTable::default()
+        };
+        for sensor in sensors.iter() {
+            result_table.register(sensor.id(), &sensor.quantities());
+        }
+        let read_counters = sensors
+            .iter()
+            .map(|sensor| (sensor.id().to_string(), ReadCounters::default()))
+            .collect();
+        let recorder = config.recorder.path.as_ref().and_then(|path| {
+            let columns = result_table.columns().iter().map(|it| it.name.clone()).collect();
+            let settings = &config.recorder;
+            let max_age = Duration::from_secs(settings.max_age_secs);
+            Recorder::new(path, settings.max_bytes, max_age, settings.keep_files, columns)
+                .map_err(|err| println!("Recorder disabled: {}", err))
+                .ok()
+        });
+        let (command_tx, command_rx) = mpsc::channel::<ServerMessage>(5);
+        let display = display.map(|display| RefreshPolicy::new(display, config.display.refresh.clone()));
+        let display_refreshes = display.as_ref().map(|display| display.counters());
+        let display = display.and_then(|display| {
+            HardwareWorker::spawn("display", Box::new(display) as Box<dyn DisplayBackend>)
+                .map_err(|err| println!("Display disabled: {}", err))
+                .ok()
+        });
 
         Engine {
-            args,
-            dht22_fs_temp,
-            dht22_fs_humidity,
+            config,
             net_connector,
             display,
-            aht20,
-            bmp280,
+            display_refreshes,
+            sensors,
+            read_counters,
+            recorder,
             result_table,
+            history: History::default(),
+            page: 0,
+            started: Instant::now(),
+            command_tx,
+            command_rx: Some(command_rx),
         }
     }
     pub async fn start_backgrund_tasks(&mut self) {
-        let settings = NetConnectorSettings::new(
-            self.args.id_device.clone(),
-            self.args.host_mqqt.clone(),
-            self.args.port_mqqt,
-            self.args
-                .username_mqqt
-                .clone()
-                .unwrap_or("theserver".to_string()),
-            self.args
-                .password_mqqt
-                .clone()
-                .unwrap_or("myserverpass".to_string()),
-        );
-        self.net_connector = Some(NetConnector::start_thread(settings).await);
+        let settings = NetConnectorSettings::from_config(&self.config);
+        let net_connector = NetConnector::start_thread(settings, self.command_tx.clone()).await;
+        net_connector.set_demo_switch(self.result_table.demo_switch);
+        self.net_connector = Some(net_connector);
     }
 
-    pub async fn run(&mut self) {
-        let mut dht22_timer = EnterTimerGuard::new(Duration::from_secs(5));
-        let mut aht20_timer = EnterTimerGuard::new(Duration::from_secs(5));
-        let mut bmp280_timer = EnterTimerGuard::new(Duration::from_secs(5));
-        let mut print_timer = EnterTimerGuard::new(Duration::from_secs(8));
-        let mut spidisplay_timer = EnterTimerGuard::new(Duration::from_secs(16));
+    /// Samples, displays and sends until `shutdown` turns true, then stops the device cleanly.
+    ///
+    /// Every sensor is read by its own task on its own interval, the loop only reacts to events:
+    /// readings, server commands, the print/display/send/health schedules and the shutdown signal.
+    pub async fn run(&mut self, mut shutdown: watch::Receiver<bool>) {
+        let Some(mut commands) = self.command_rx.take() else {
+            println!("Engine is already running");
+            return;
+        };
+
+        let (reading_tx, mut readings) = mpsc::channel::<SensorEvent>(16);
+        let read_timeout = Duration::from_secs(self.config.timeouts.sensor_read_secs);
+        let sensor_tasks: Vec<JoinHandle<()>> = self
+            .sensors
+            .drain(..)
+            .filter_map(|sensor| {
+                let sensor_id = sensor.id().to_string();
+                let period = self.config.sensors.interval(&sensor_id);
+                let schedule = sensor.schedule();
+                match HardwareWorker::spawn(&sensor_id, sensor) {
+                    Ok(worker) => Some(tokio::spawn(sample_sensor(
+                        worker,
+                        period,
+                        schedule,
+                        read_timeout,
+                        reading_tx.clone(),
+                    ))),
+                    Err(err) => {
+                        println!("{} disabled: {}", sensor_id, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        drop(reading_tx);
 
-        let mut send_timer = EnterTimerGuard::new(Duration::from_secs(16));
+        let intervals = self.config.intervals.clone();
+        let mut print_timer = ticker(Duration::from_secs(intervals.print_secs));
+        let mut display_timer = ticker(Duration::from_secs(intervals.display_secs));
+        let mut send_timer = ticker(Duration::from_secs(intervals.send_secs));
+        let mut health_timer = ticker(Duration::from_secs(self.config.health.interval_secs));
+        let rotate_pages = self.display.is_some() && self.config.display.pages.len() > 1;
+        let mut page_timer = ticker(Duration::from_secs(self.config.display.page_secs.max(1)));
+        let mut snapshot_requests = signal(SignalKind::user_defined1())
+            .map_err(|err| println!("SIGUSR1 handler not installed: {}", err))
+            .ok();
 
         loop {
-            tokio::time::sleep(Duration::from_secs(2)).await; //temp
-
-            if dht22_timer.enter() {
-                self.get_dht22();
+            tokio::select! {
+                event = readings.recv(), if !sensor_tasks.is_empty() => match event {
+                    Some(event) => self.handle_reading(event),
+                    //only a replay runs out of readings
+                    None => {
+                        println!("Every sensor finished, stopping");
+                        break;
+                    }
+                },
+                Some(command) = commands.recv() => {
+                    self.handle_command(command);
+                    //show the new state now, the next scheduled refresh moves one period away
+                    self.update_display();
+                    display_timer.reset();
+                }
+                _ = print_timer.tick() => println!("{}", self.result_table),
+                _ = display_timer.tick() => self.update_display(),
+                _ = page_timer.tick(), if rotate_pages => {
+                    self.page = (self.page + 1) % self.config.display.pages.len();
+                    self.update_display();
+                    display_timer.reset();
+                }
+                _ = send_timer.tick() => {
+                    if let Some(net_connector) = self.net_connector.as_ref() {
+                        net_connector.send_data(self.result_table.clone()).await;
+                    }
+                }
+                _ = health_timer.tick(), if self.config.health.enabled => self.send_health(),
+                Some(()) = next_signal(&mut snapshot_requests) => self.write_snapshot(),
+                _ = shutdown.wait_for(|it| *it) => break,
             }
+        }
 
-            if aht20_timer.enter() {
-                let _ = self.get_aht20();
-            }
+        for task in sensor_tasks {
+            task.abort();
+        }
+        self.shutdown().await;
+    }
 
-            if bmp280_timer.enter() {
-                let _ = self.get_bmp280();
-            }
+    fn handle_command(&mut self, command: ServerMessage) {
+        match command.command() {
+            Cmd::Check => self.result_table.demo_switch = true,
+            Cmd::Uncheck => self.result_table.demo_switch = false,
+            Cmd::Switch => self.result_table.demo_switch = !self.result_table.demo_switch,
+        };
+        if let Some(net_connector) = self.net_connector.as_ref() {
+            net_connector.set_demo_switch(self.result_table.demo_switch);
+        }
+    }
 
-            if print_timer.enter() {
-                println!("{:?}", self.result_table);
-            }
+    fn handle_reading(&mut self, event: SensorEvent) {
+        let counters = self
+            .read_counters
+            .iter_mut()
+            .find(|(id, _)| *id == event.sensor_id)
+            .map(|(_, counters)| counters);
 
-            //check messages from MQTT
-            self.handle_recv(&mut spidisplay_timer);
+        match event.result {
+            Ok(readings) => {
+                if let Some(counters) = counters {
+                    counters.successes += 1;
+                }
+                self.result_table.apply(&event.sensor_id, &readings);
+                self.history.record(
+                    SystemTime::now(),
+                    self.result_table.air_average(Quantity::Temperature),
+                    self.result_table.air_average(Quantity::Humidity),
+                    self.result_table.air_average(Quantity::Pressure),
+                );
 
-            if spidisplay_timer.enter() {
-                self.display.update(self.result_table);
+                if let Some(recorder) = self.recorder.as_mut() {
+                    if let Err(err) = recorder.record(&event.sensor_id, &self.result_table) {
+                        println!("Recorder error: {}", err);
+                    }
+                }
             }
-
-            if send_timer.enter() {
-                self.net_connector
-                    .as_ref()
-                    .unwrap()
-                    .send_data(self.result_table.clone())
-                    .await;
+            Err(err) => {
+                if let Some(counters) = counters {
+                    counters.failures += 1;
+                }
+                println!("{} read error: {}", event.sensor_id, err);
+                self.result_table.mark_failed(&event.sensor_id);
             }
         }
     }
 
-    //check messages from MQTT
-    fn handle_recv(&mut self, display_timer: &mut EnterTimerGuard) {
-        match self.net_connector.as_mut().unwrap().receiver.try_recv() {
-            Ok(res) => {
-                match res.command() {
-                    crate::proto::proto_broker_msgs::server_message::Cmd::Check => {
-                        self.result_table.demo_switch = true
-                    }
-                    crate::proto::proto_broker_msgs::server_message::Cmd::Uncheck => {
-                        self.result_table.demo_switch = false
-                    }
-                    crate::proto::proto_broker_msgs::server_message::Cmd::Switch => {
-                        self.result_table.demo_switch = !self.result_table.demo_switch
-                    }
-                };
-                display_timer.force_next_enter();
+    //starts a refresh in the background, skipped while the previous one is still running
+    fn update_display(&self) {
+        let Some(display) = self.display.clone() else {
+            return;
+        };
+        let screen = self.screen();
+        let timeout = Duration::from_secs(self.config.timeouts.display_refresh_secs);
+        tokio::spawn(async move {
+            match display.call(timeout, move |display| display.show(&screen)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) | Err(err) => println!("{}", err),
             }
-            Err(err) => match err {
-                tokio::sync::mpsc::error::TryRecvError::Disconnected => {
-                    panic!("mpsc with the net_connector thread has been disconnected")
-                }
-                _ => {}
-            },
+        });
+    }
+
+    //the current page with the latest values
+    fn screen(&self) -> Screen {
+        let page = self.config.display.pages.get(self.page).copied().unwrap_or(DisplayPage::Values);
+        match page {
+            DisplayPage::Values => Screen::values(&self.result_table),
+            DisplayPage::Sensors => Screen::sensors(&self.result_table),
+            DisplayPage::History => Screen::history(&self.history),
+            DisplayPage::MinMax => Screen::min_max(&self.history, SystemTime::now()),
+            DisplayPage::Status => Screen::status(
+                &self.config.device.id,
+                health::local_ip(&self.config.broker.host),
+                self.net_connector.as_ref().map(|it| it.is_connected()),
+                self.net_connector.as_ref().and_then(|it| it.last_delivery()),
+            ),
         }
     }
 
-    fn get_dht22(&mut self) {
-        let _ = std::fs::read_to_string(self.dht22_fs_temp.as_str()).and_then(|it| {
-            let _ = it.trim_end().parse::<f32>().and_then(|parsed| {
-                self.result_table.dht22_temp = parsed / 1000.0;
-                Ok(())
-            });
-            Ok(())
-        });
+    //SIGUSR1, draws the current page like a refresh would, also without a display attached
+    fn write_snapshot(&self) {
+        let Some(path) = self.config.display.snapshot_path.as_ref() else {
+            println!("Snapshot requested but display.snapshot_path is not set");
+            return;
+        };
+        let screen = self.screen();
+        match display::write_snapshot(&screen, display::panel_size(&self.config.display), path) {
+            Ok(()) => println!("Snapshot written to {}", path.display()),
+            Err(err) => println!("{}", err),
+        }
+    }
 
-        let _ = std::fs::read_to_string(self.dht22_fs_humidity.as_str()).and_then(|it| {
-            let _ = it.trim_end().parse::<f32>().and_then(|parsed| {
-                self.result_table.dht22_humidity = parsed / 1000.0;
-                Ok(())
-            });
-            Ok(())
-        });
+    async fn shutdown(&mut self) {
+        println!("Shutting down");
+        if let Some(net_connector) = self.net_connector.take() {
+            net_connector
+                .shutdown(Duration::from_secs(self.config.shutdown.flush_timeout_secs))
+                .await;
+        }
+        if self.config.display.stopped_screen {
+            if let Some(display) = self.display.as_ref() {
+                if let Err(err) = self.show_stopped(display).await {
+                    println!("{}", err);
+                }
+            }
+        }
+        println!("Stopped");
     }
 
-    fn get_aht20(&mut self) -> Result<(), embedded_aht20::Error<rppal::i2c::Error>> {
-        let result = self.aht20.measure()?;
-        self.result_table.aht20_temp = result.temperature.celcius();
-        self.result_table.aht20_humidity = result.relative_humidity;
+    //waits for a running refresh to finish first
+    async fn show_stopped(&self, display: &HardwareWorker<Box<dyn DisplayBackend>>) -> DeviceResult<()> {
+        let timeout = Duration::from_secs(self.config.timeouts.display_refresh_secs);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let screen = Screen::stopped(SystemTime::now());
+            match display.call(timeout, move |display| display.show(&screen)).await {
+                Err(DeviceError::Worker { .. }) if Instant::now() < deadline => {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                result => return result.and_then(|it| it),
+            }
+        }
+    }
 
-        Ok(())
+    fn send_health(&self) {
+        let Some(net_connector) = self.net_connector.as_ref() else {
+            return;
+        };
+        let health = &self.config.health;
+        let stats = SystemStats::read(&health.thermal_root, &health.proc_root);
+        let sensors: Vec<(&str, ReadCounters)> = self
+            .read_counters
+            .iter()
+            .map(|(id, counters)| (id.as_str(), *counters))
+            .collect();
+        let display_refreshes = self
+            .display_refreshes
+            .as_ref()
+            .map(|counters| *counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        net_connector.send_health(self.started.elapsed(), &sensors, stats, display_refreshes);
     }
+}
 
-    fn get_bmp280(&mut self) -> Result<(), Box<dyn Error>> {
-        self.result_table.bmp280_temp = self.bmp280.temperature_celsius()?;
-        self.result_table.bmp280_pressure = self.bmp280.pressure_kpa()?;
+//reads one sensor on its own schedule and thread, a slow or hung sensor delays only its own readings
+//
+//a sensor with its own schedule is read at those offsets and the task ends after the last one
+async fn sample_sensor(
+    sensor: HardwareWorker<Box<dyn Sensor>>,
+    period: Duration,
+    schedule: Option<Vec<Duration>>,
+    read_timeout: Duration,
+    events: mpsc::Sender<SensorEvent>,
+) {
+    let start = tokio::time::Instant::now();
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut schedule = schedule.map(|it| it.into_iter());
+    loop {
+        match schedule.as_mut() {
+            None => {
+                interval.tick().await;
+            }
+            Some(schedule) => match schedule.next() {
+                Some(offset) => tokio::time::sleep_until(start + offset).await,
+                None => {
+                    println!("{}: end of trace", sensor.name());
+                    return;
+                }
+            },
+        }
+        let result = sensor.call(read_timeout, |sensor| sensor.read()).await;
+        let event = SensorEvent {
+            sensor_id: sensor.name().to_string(),
+            result: result.and_then(|it| it),
+        };
+        if events.send(event).await.is_err() {
+            return;
+        }
+    }
+}
+
+//first tick after one period, like the timers of the old polling loop
+fn ticker(period: Duration) -> Interval {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval
+}
 
-        Ok(())
+//waits forever when the handler could not be installed
+async fn next_signal(signal: &mut Option<Signal>) -> Option<()> {
+    match signal {
+        Some(signal) => signal.recv().await,
+        None => std::future::pending().await,
     }
 }
@@ -0,0 +1,102 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+
+use crate::error::{DeviceError, DeviceResult};
+
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Owns a blocking device (I2C sensor, SPI display) on a dedicated thread.
+///
+/// Async code sends requests with `call` and waits for the result with a timeout, so a hung
+/// device stalls only its own thread. A request that times out keeps the worker busy until
+/// the device returns, further requests fail right away instead of queueing behind it.
+pub struct HardwareWorker<T> {
+    name: String,
+    jobs: mpsc::Sender<Job<T>>,
+    busy: Arc<AtomicBool>,
+}
+
+impl<T> Clone for HardwareWorker<T> {
+    fn clone(&self) -> Self {
+        HardwareWorker {
+            name: self.name.clone(),
+            jobs: self.jobs.clone(),
+            busy: self.busy.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> HardwareWorker<T> {
+    pub fn spawn(name: &str, mut device: T) -> DeviceResult<HardwareWorker<T>> {
+        let (jobs, job_rx) = mpsc::channel::<Job<T>>();
+        let busy = Arc::new(AtomicBool::new(false));
+
+        let thread_busy = busy.clone();
+        thread::Builder::new()
+            .name(format!("hw-{}", name))
+            .spawn(move || {
+                //ends when every handle is dropped
+                for job in job_rx {
+                    //a panicking driver fails this request only, the reply channel is dropped with it
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&mut device)));
+                    thread_busy.store(false, Ordering::Release);
+                }
+            })
+            .map_err(|err| DeviceError::io(format!("start {} worker thread", name), err))?;
+
+        Ok(HardwareWorker {
+            name: name.to_string(),
+            jobs,
+            busy,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Runs `job` on the worker thread and waits at most `timeout` for its result.
+    pub async fn call<R, F>(&self, timeout: Duration, job: F) -> DeviceResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> R + Send + 'static,
+    {
+        if self.busy.swap(true, Ordering::AcqRel) {
+            return Err(self.error("still busy with the previous request"));
+        }
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let job: Job<T> = Box::new(move |device| {
+            let _ = reply_tx.send(job(device));
+        });
+        if self.jobs.send(job).is_err() {
+            self.busy.store(false, Ordering::Release);
+            return Err(self.error("worker thread stopped"));
+        }
+
+        match tokio::time::timeout(timeout, reply_rx).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(_)) => Err(self.error("request panicked")),
+            Err(_) => Err(DeviceError::Timeout {
+                device: self.name.clone(),
+                after: timeout,
+            }),
+        }
+    }
+
+    fn error(&self, message: &str) -> DeviceError {
+        DeviceError::Worker {
+            device: self.name.clone(),
+            message: message.to_string(),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket},
+    path::Path,
+};
+
+/// Read counters of one sensor since the start of the process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadCounters {
+    pub successes: u64,
+    pub failures: u64,
+}
+
+/// Display refreshes since the start of the process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RefreshCounters {
+    pub full: u64,
+    pub partial: u64,
+    //nothing changed by more than the deadbands
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// Board statistics from the thermal sysfs class and procfs, a value is None when the board does not expose it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemStats {
+    pub soc_temperature: Option<f32>,
+    //1, 5 and 15 minutes
+    pub load_average: Option<[f32; 3]>,
+    pub memory_available_bytes: Option<u64>,
+    pub memory_total_bytes: Option<u64>,
+}
+
+impl SystemStats {
+    pub fn read(thermal_root: &Path, proc_root: &Path) -> SystemStats {
+        let (memory_available_bytes, memory_total_bytes) = read_memory(proc_root);
+        SystemStats {
+            soc_temperature: read_soc_temperature(thermal_root),
+            load_average: read_load_average(proc_root),
+            memory_available_bytes,
+            memory_total_bytes,
+        }
+    }
+}
+
+//the zone named like the CPU or SoC, the first zone otherwise; the Raspberry Pi has a single "cpu-thermal"
+fn read_soc_temperature(thermal_root: &Path) -> Option<f32> {
+    let mut zones: Vec<_> = fs::read_dir(thermal_root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|it| it.to_str())
+                .is_some_and(|it| it.starts_with("thermal_zone"))
+        })
+        .collect();
+    zones.sort();
+
+    let zone = zones
+        .iter()
+        .find(|zone| {
+            fs::read_to_string(zone.join("type"))
+                .map(|kind| {
+                    let kind = kind.trim().to_lowercase();
+                    kind.contains("cpu") || kind.contains("soc")
+                })
+                .unwrap_or(false)
+        })
+        .or(zones.first())?;
+
+    //millidegrees Celsius
+    let millidegrees: i64 = fs::read_to_string(zone.join("temp")).ok()?.trim().parse().ok()?;
+    Some(millidegrees as f32 / 1000.0)
+}
+
+//"0.42 0.37 0.30 1/123 4567"
+fn read_load_average(proc_root: &Path) -> Option<[f32; 3]> {
+    let content = fs::read_to_string(proc_root.join("loadavg")).ok()?;
+    let mut values = content.split_whitespace().map(|it| it.parse::<f32>().ok());
+    Some([values.next()??, values.next()??, values.next()??])
+}
+
+//MemAvailable and MemTotal from meminfo, the file reports kB
+fn read_memory(proc_root: &Path) -> (Option<u64>, Option<u64>) {
+    let Ok(content) = fs::read_to_string(proc_root.join("meminfo")) else {
+        return (None, None);
+    };
+    let field = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.split_whitespace().next()?.parse::<u64>().ok())
+            .map(|kilobytes| kilobytes * 1024)
+    };
+    (field("MemAvailable"), field("MemTotal"))
+}
+
+/// Address of the interface that routes to the broker, or of the default route when the broker is a host name.
+///
+/// Connecting a UDP socket only looks up the route, nothing is sent and no name is resolved.
+pub fn local_ip(broker_host: &str) -> Option<IpAddr> {
+    let target = broker_host
+        .parse::<IpAddr>()
+        .unwrap_or(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)));
+    let bind = match target {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((bind, 0)).ok()?;
+    socket.connect((target, 53)).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(root: &Path, name: &str, kind: &str, temp: &str) {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+        fs::write(dir.join("temp"), format!("{}\n", temp)).unwrap();
+    }
+
+    #[test]
+    fn picks_the_cpu_thermal_zone() {
+        let root = tempfile::tempdir().unwrap();
+        zone(root.path(), "thermal_zone0", "battery", "30000");
+        zone(root.path(), "thermal_zone1", "cpu-thermal", "48312");
+        fs::create_dir(root.path().join("cooling_device0")).unwrap();
+
+        assert_eq!(read_soc_temperature(root.path()), Some(48.312));
+    }
+
+    #[test]
+    fn falls_back_to_the_first_thermal_zone() {
+        let root = tempfile::tempdir().unwrap();
+        zone(root.path(), "thermal_zone1", "gpu", "60000");
+        zone(root.path(), "thermal_zone0", "acpitz", "41000");
+
+        assert_eq!(read_soc_temperature(root.path()), Some(41.0));
+    }
+
+    #[test]
+    fn missing_or_garbled_thermal_zone_gives_none() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(read_soc_temperature(&root.path().join("missing")), None);
+        assert_eq!(read_soc_temperature(root.path()), None);
+
+        zone(root.path(), "thermal_zone0", "cpu-thermal", "hot");
+        assert_eq!(read_soc_temperature(root.path()), None);
+    }
+
+    #[test]
+    fn parses_loadavg() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("loadavg"), "0.42 0.37 0.30 1/123 4567\n").unwrap();
+
+        assert_eq!(read_load_average(root.path()), Some([0.42, 0.37, 0.30]));
+    }
+
+    #[test]
+    fn missing_or_garbled_loadavg_gives_none() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(read_load_average(root.path()), None);
+
+        fs::write(root.path().join("loadavg"), "0.42 0.37\n").unwrap();
+        assert_eq!(read_load_average(root.path()), None);
+
+        fs::write(root.path().join("loadavg"), "0.42 high 0.30 1/123 4567\n").unwrap();
+        assert_eq!(read_load_average(root.path()), None);
+    }
+
+    #[test]
+    fn parses_meminfo() {
+        let root = tempfile::tempdir().unwrap();
+        let meminfo = concat!(
+            "MemTotal:        3884096 kB\n",
+            "MemFree:          812344 kB\n",
+            "MemAvailable:    2871812 kB\n",
+            "Buffers:           88316 kB\n",
+        );
+        fs::write(root.path().join("meminfo"), meminfo).unwrap();
+
+        assert_eq!(read_memory(root.path()), (Some(2871812 * 1024), Some(3884096 * 1024)));
+    }
+
+    #[test]
+    fn missing_or_garbled_meminfo_gives_none() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(read_memory(root.path()), (None, None));
+
+        //an old kernel without MemAvailable and a garbled MemTotal
+        fs::write(root.path().join("meminfo"), "MemTotal: lots kB\nMemFree: 812344 kB\n").unwrap();
+        assert_eq!(read_memory(root.path()), (None, None));
+    }
+
+    #[test]
+    fn reads_every_value_of_a_board() {
+        let root = tempfile::tempdir().unwrap();
+        let thermal = root.path().join("thermal");
+        let proc = root.path().join("proc");
+        fs::create_dir_all(&proc).unwrap();
+        zone(&thermal, "thermal_zone0", "cpu-thermal", "51000");
+        fs::write(proc.join("loadavg"), "1.00 0.50 0.25 2/200 100\n").unwrap();
+        fs::write(proc.join("meminfo"), "MemTotal: 1000 kB\nMemAvailable: 400 kB\n").unwrap();
+
+        let stats = SystemStats::read(&thermal, &proc);
+        assert_eq!(
+            stats,
+            SystemStats {
+                soc_temperature: Some(51.0),
+                load_average: Some([1.0, 0.5, 0.25]),
+                memory_available_bytes: Some(400 * 1024),
+                memory_total_bytes: Some(1000 * 1024),
+            }
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Number of sparkline points, 24 h in 15 minute buckets.
+pub const HISTORY_POINTS: usize = 96;
+const BUCKET: Duration = Duration::from_secs(15 * 60);
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Lowest and highest value of a quantity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Range {
+    /// `range` widened to include `value`.
+    pub fn extend(range: Option<Range>, value: Option<f32>) -> Option<Range> {
+        let Some(value) = value else {
+            return range;
+        };
+        Some(match range {
+            Some(range) => Range {
+                min: range.min.min(value),
+                max: range.max.max(value),
+            },
+            None => Range { min: value, max: value },
+        })
+    }
+}
+
+/// Extremes of the air averages since midnight UTC, None for a quantity that was not measured today.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MinMax {
+    pub temperature: Option<Range>,
+    pub humidity: Option<Range>,
+    pub pressure: Option<Range>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Mean {
+    sum: f64,
+    count: u32,
+}
+
+impl Mean {
+    fn add(&mut self, value: Option<f32>) {
+        if let Some(value) = value {
+            self.sum += f64::from(value);
+            self.count += 1;
+        }
+    }
+
+    fn value(&self) -> Option<f32> {
+        (self.count > 0).then(|| (self.sum / f64::from(self.count)) as f32)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenBucket {
+    //bucket number since the epoch
+    index: u64,
+    temperature: Mean,
+    humidity: Mean,
+}
+
+/// In-memory history of the air averages for the display pages, it starts empty on every start.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    //closed buckets oldest first, None where nothing was measured
+    points: VecDeque<(Option<f32>, Option<f32>)>,
+    open: Option<OpenBucket>,
+    //day number since the epoch and its extremes
+    today: Option<(u64, MinMax)>,
+}
+
+impl History {
+    pub fn record(&mut self, at: SystemTime, temperature: Option<f32>, humidity: Option<f32>, pressure: Option<f32>) {
+        let secs = at.duration_since(UNIX_EPOCH).map(|it| it.as_secs()).unwrap_or(0);
+
+        let index = secs / BUCKET.as_secs();
+        match self.open.take() {
+            Some(open) if open.index == index => self.open = Some(open),
+            open => {
+                if let Some(open) = open {
+                    self.close(open, index);
+                }
+                self.open = Some(OpenBucket {
+                    index,
+                    temperature: Mean::default(),
+                    humidity: Mean::default(),
+                });
+            }
+        }
+        if let Some(open) = self.open.as_mut() {
+            open.temperature.add(temperature);
+            open.humidity.add(humidity);
+        }
+
+        let day = secs / DAY_SECS;
+        let mut min_max = match self.today {
+            Some((today, min_max)) if today == day => min_max,
+            _ => MinMax::default(),
+        };
+        min_max.temperature = Range::extend(min_max.temperature, temperature);
+        min_max.humidity = Range::extend(min_max.humidity, humidity);
+        min_max.pressure = Range::extend(min_max.pressure, pressure);
+        self.today = Some((day, min_max));
+    }
+
+    /// Temperature and humidity points oldest first, the running bucket is the last one.
+    pub fn series(&self) -> (Vec<Option<f32>>, Vec<Option<f32>>) {
+        let open = self
+            .open
+            .map(|open| (open.temperature.value(), open.humidity.value()));
+        let points: Vec<_> = self.points.iter().copied().chain(open).collect();
+        let recent = &points[points.len().saturating_sub(HISTORY_POINTS)..];
+        recent.iter().copied().unzip()
+    }
+
+    /// Extremes of the day of `at`, empty until the first reading of that day.
+    pub fn min_max(&self, at: SystemTime) -> MinMax {
+        let day = at.duration_since(UNIX_EPOCH).map(|it| it.as_secs()).unwrap_or(0) / DAY_SECS;
+        match self.today {
+            Some((today, min_max)) if today == day => min_max,
+            _ => MinMax::default(),
+        }
+    }
+
+    fn close(&mut self, bucket: OpenBucket, next_index: u64) {
+        self.points
+            .push_back((bucket.temperature.value(), bucket.humidity.value()));
+        //buckets without a reading, the sensors failed or the clock jumped
+        let skipped = next_index
+            .saturating_sub(bucket.index + 1)
+            .min(HISTORY_POINTS as u64);
+        for _ in 0..skipped {
+            self.points.push_back((None, None));
+        }
+        while self.points.len() > HISTORY_POINTS {
+            self.points.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //midnight UTC of 2024-03-14
+    const MIDNIGHT: u64 = 19796 * DAY_SECS;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(MIDNIGHT + secs)
+    }
+
+    #[test]
+    fn averages_readings_of_one_bucket() {
+        let mut history = History::default();
+        history.record(at(0), Some(20.0), Some(40.0), None);
+        history.record(at(60), Some(22.0), None, None);
+        history.record(at(899), Some(24.0), Some(50.0), None);
+
+        assert_eq!(history.series(), (vec![Some(22.0)], vec![Some(45.0)]));
+    }
+
+    #[test]
+    fn a_new_bucket_closes_the_previous_one() {
+        let mut history = History::default();
+        history.record(at(0), Some(20.0), Some(40.0), None);
+        history.record(at(900), Some(21.0), Some(41.0), None);
+        history.record(at(1800), None, None, None);
+
+        assert_eq!(
+            history.series(),
+            (vec![Some(20.0), Some(21.0), None], vec![Some(40.0), Some(41.0), None])
+        );
+    }
+
+    #[test]
+    fn fills_skipped_buckets_with_gaps() {
+        let mut history = History::default();
+        history.record(at(0), Some(20.0), Some(40.0), None);
+        //three buckets without a reading
+        history.record(at(4 * 900 + 10), Some(23.0), Some(43.0), None);
+
+        let (temperatures, humidities) = history.series();
+        assert_eq!(temperatures, vec![Some(20.0), None, None, None, Some(23.0)]);
+        assert_eq!(humidities, vec![Some(40.0), None, None, None, Some(43.0)]);
+    }
+
+    #[test]
+    fn keeps_the_last_96_points() {
+        let mut history = History::default();
+        for bucket in 0..200u64 {
+            history.record(at(bucket * 900), Some(bucket as f32), None, None);
+        }
+
+        let (temperatures, humidities) = history.series();
+        assert_eq!(temperatures.len(), HISTORY_POINTS);
+        assert_eq!(temperatures.first(), Some(&Some(104.0)));
+        assert_eq!(temperatures.last(), Some(&Some(199.0)));
+        assert_eq!(humidities, vec![None; HISTORY_POINTS]);
+    }
+
+    #[test]
+    fn a_long_gap_leaves_only_the_latest_bucket() {
+        let mut history = History::default();
+        history.record(at(0), Some(20.0), None, None);
+        history.record(at(3 * DAY_SECS), Some(25.0), None, None);
+
+        let (temperatures, _) = history.series();
+        assert_eq!(temperatures.len(), HISTORY_POINTS);
+        assert!(temperatures[..HISTORY_POINTS - 1].iter().all(|it| it.is_none()));
+        assert_eq!(temperatures.last(), Some(&Some(25.0)));
+    }
+
+    #[test]
+    fn tracks_min_max_of_the_day() {
+        let mut history = History::default();
+        history.record(at(3600), Some(18.5), Some(60.0), Some(1013.0));
+        history.record(at(7200), Some(24.0), None, Some(1009.5));
+        history.record(at(10800), Some(21.0), Some(55.0), None);
+
+        assert_eq!(
+            history.min_max(at(10800)),
+            MinMax {
+                temperature: Some(Range { min: 18.5, max: 24.0 }),
+                humidity: Some(Range { min: 55.0, max: 60.0 }),
+                pressure: Some(Range { min: 1009.5, max: 1013.0 }),
+            }
+        );
+    }
+
+    #[test]
+    fn min_max_starts_over_at_midnight() {
+        let mut history = History::default();
+        history.record(at(DAY_SECS - 60), Some(10.0), Some(80.0), Some(1000.0));
+        assert_eq!(history.min_max(at(DAY_SECS - 1)).temperature, Some(Range { min: 10.0, max: 10.0 }));
+        //the next day is empty until its first reading
+        assert_eq!(history.min_max(at(DAY_SECS)), MinMax::default());
+
+        history.record(at(DAY_SECS + 60), Some(12.0), None, None);
+        assert_eq!(
+            history.min_max(at(DAY_SECS + 60)),
+            MinMax {
+                temperature: Some(Range { min: 12.0, max: 12.0 }),
+                humidity: None,
+                pressure: None,
+            }
+        );
+        //the previous day is gone
+        assert_eq!(history.min_max(at(DAY_SECS - 1)), MinMax::default());
+    }
+
+    #[test]
+    fn empty_history() {
+        let history = History::default();
+        assert_eq!(history.series(), (vec![], vec![]));
+        assert_eq!(history.min_max(at(0)), MinMax::default());
+    }
+}
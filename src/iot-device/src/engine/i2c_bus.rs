@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use embedded_hal::i2c::{ErrorType, Operation, SevenBitAddress};
+use rppal::i2c::I2c;
+
+use crate::error::{DeviceError, DeviceResult};
+
+/// Single owner of every I2C adapter the device uses.
+///
+/// Drivers never open `/dev/i2c-N` themselves, they get a `SharedI2c` from `device`. Each adapter
+/// is opened once and its transactions are serialized, and two drivers claiming the same address
+/// on the same bus are reported instead of silently talking over each other.
+#[derive(Default)]
+pub struct I2cBusManager {
+    buses: HashMap<u8, Arc<Mutex<I2c>>>,
+    //(bus, address) -> driver that claimed it
+    claimed: HashMap<(u8, u8), String>,
+}
+
+impl I2cBusManager {
+    pub fn new() -> I2cBusManager {
+        I2cBusManager::default()
+    }
+
+    /// Handle for the driver `owner` talking to `address` on `/dev/i2c-<bus>`.
+    pub fn device(&mut self, bus: u8, address: u8, owner: &str) -> DeviceResult<SharedI2c> {
+        if let Some(other) = self.claimed.get(&(bus, address)) {
+            return Err(DeviceError::Config(format!(
+                "{} and {} both use address {:#04x} on I2C bus {}",
+                other, owner, address, bus
+            )));
+        }
+
+        let handle = self.bus(bus)?;
+        self.claimed.insert((bus, address), owner.to_string());
+        Ok(handle)
+    }
+
+    /// Handle for the whole adapter without claiming an address, used to probe the bus.
+    pub fn bus(&mut self, bus: u8) -> DeviceResult<SharedI2c> {
+        if let Some(handle) = self.buses.get(&bus) {
+            return Ok(SharedI2c { bus: handle.clone() });
+        }
+
+        let i2c = I2c::with_bus(bus).map_err(|source| DeviceError::I2c {
+            context: format!("open bus {}", bus),
+            source,
+        })?;
+        let handle = Arc::new(Mutex::new(i2c));
+        self.buses.insert(bus, handle.clone());
+        Ok(SharedI2c { bus: handle })
+    }
+
+    /// Adapters opened so far.
+    pub fn buses(&self) -> Vec<u8> {
+        let mut buses: Vec<u8> = self.buses.keys().copied().collect();
+        buses.sort_unstable();
+        buses
+    }
+}
+
+/// embedded-hal I2C device on a shared adapter, every transaction holds the bus lock for its whole length.
+#[derive(Clone)]
+pub struct SharedI2c {
+    bus: Arc<Mutex<I2c>>,
+}
+
+impl ErrorType for SharedI2c {
+    type Error = rppal::i2c::Error;
+}
+
+impl embedded_hal::i2c::I2c for SharedI2c {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        //a driver that panicked mid-transaction left no state behind in the adapter handle
+        let mut bus = self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        bus.transaction(address, operations)
+    }
+}
+
+//for the OLED drivers
+impl embedded_hal_02::blocking::i2c::Write for SharedI2c {
+    type Error = rppal::i2c::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        embedded_hal::i2c::I2c::write(self, address, bytes)
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+use embedded_hal::i2c::I2c;
+
+use super::i2c_bus::I2cBusManager;
+
+//7-bit addresses outside the reserved ranges, the same range i2cdetect probes
+const FIRST_ADDRESS: u8 = 0x08;
+const LAST_ADDRESS: u8 = 0x77;
+
+const AHT20_ADDRESS: u8 = 0x38;
+const BMP280_ADDRESSES: [u8; 2] = [0x76, 0x77];
+const BOSCH_CHIP_ID_REGISTER: u8 = 0xD0;
+
+/// Chips `scan_bus` can tell apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip {
+    Aht20,
+    Bmp280,
+    //answer at the BMP280 addresses with a different chip id, no driver yet
+    Bme280,
+    Bmp180,
+    Unknown,
+}
+
+/// A device that acknowledged its address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detected {
+    pub bus: u8,
+    pub address: u8,
+    pub chip: Chip,
+}
+
+impl fmt::Display for Detected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "i2c-{} {:#04x}: ", self.bus, self.address)?;
+        match (self.chip, hint(self.address)) {
+            (Chip::Aht20, _) => write!(f, "aht20"),
+            (Chip::Bmp280, _) => write!(f, "bmp280"),
+            (Chip::Bme280, _) => write!(f, "bme280 (not supported)"),
+            (Chip::Bmp180, _) => write!(f, "bmp180 (not supported)"),
+            (Chip::Unknown, Some(hint)) => write!(f, "unknown, possibly {}", hint),
+            (Chip::Unknown, None) => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Probes every address of `i2c`, the adapter of `bus`, with a one byte read and identifies the known chips.
+pub fn scan_bus<I: I2c>(i2c: &mut I, bus: u8) -> Vec<Detected> {
+    let mut found = Vec::new();
+    for address in FIRST_ADDRESS..=LAST_ADDRESS {
+        let mut probe = [0u8; 1];
+        if i2c.read(address, &mut probe).is_err() {
+            continue;
+        }
+        found.push(Detected {
+            bus,
+            address,
+            chip: identify(i2c, address),
+        });
+    }
+    found
+}
+
+/// Scans `buses` and prints what answered, returns false when a bus could not be opened.
+pub fn print_scan(buses: &[u8]) -> bool {
+    let mut manager = I2cBusManager::new();
+    let mut all_opened = true;
+    for bus in buses {
+        match manager.bus(*bus).map(|mut i2c| scan_bus(&mut i2c, *bus)) {
+            Ok(found) if found.is_empty() => println!("i2c-{}: no devices", bus),
+            Ok(found) => {
+                for detected in found {
+                    println!("{}", detected);
+                }
+            }
+            Err(err) => {
+                println!("i2c-{}: {}", bus, err);
+                all_opened = false;
+            }
+        }
+    }
+    all_opened
+}
+
+fn identify<I: I2c>(i2c: &mut I, address: u8) -> Chip {
+    if address == AHT20_ADDRESS {
+        //the address is fixed, anything answering there is an AHT10/AHT20/AHT21 which share the protocol
+        return Chip::Aht20;
+    }
+    if BMP280_ADDRESSES.contains(&address) {
+        let mut chip_id = [0u8; 1];
+        if i2c
+            .write_read(address, &[BOSCH_CHIP_ID_REGISTER], &mut chip_id)
+            .is_ok()
+        {
+            return match chip_id[0] {
+                0x58 => Chip::Bmp280,
+                0x60 => Chip::Bme280,
+                0x55 => Chip::Bmp180,
+                _ => Chip::Unknown,
+            };
+        }
+    }
+    Chip::Unknown
+}
+
+//common breakout boards at their default addresses
+fn hint(address: u8) -> Option<&'static str> {
+    match address {
+        0x23 => Some("BH1750"),
+        0x3C | 0x3D => Some("SSD1306/SH1106 OLED"),
+        0x40 => Some("HTU21D/Si7021/INA219"),
+        0x44 | 0x45 => Some("SHT3x"),
+        0x48 => Some("ADS1115/TMP102"),
+        0x5C => Some("AM2320"),
+        0x68 => Some("DS3231/MPU6050"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation, SevenBitAddress};
+
+    use super::*;
+
+    //devices by address, each answers a register read with its chip id
+    struct MockBus {
+        devices: HashMap<u8, u8>,
+    }
+
+    impl MockBus {
+        fn new(devices: &[(u8, u8)]) -> MockBus {
+            MockBus {
+                devices: devices.iter().copied().collect(),
+            }
+        }
+    }
+
+    impl ErrorType for MockBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockBus {
+        fn transaction(
+            &mut self,
+            address: SevenBitAddress,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            let chip_id = *self
+                .devices
+                .get(&address)
+                .ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))?;
+            let mut register = None;
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => register = bytes.first().copied(),
+                    Operation::Read(buffer) => {
+                        let value = if register == Some(BOSCH_CHIP_ID_REGISTER) { chip_id } else { 0 };
+                        buffer.fill(value);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn chips(devices: &[(u8, u8)]) -> Vec<(u8, Chip)> {
+        scan_bus(&mut MockBus::new(devices), 1)
+            .into_iter()
+            .map(|it| (it.address, it.chip))
+            .collect()
+    }
+
+    #[test]
+    fn identifies_the_known_chips() {
+        let found = chips(&[(0x38, 0x00), (0x76, 0x58), (0x77, 0x60)]);
+        assert_eq!(found, vec![(0x38, Chip::Aht20), (0x76, Chip::Bmp280), (0x77, Chip::Bme280)]);
+    }
+
+    #[test]
+    fn tells_bosch_chips_apart_by_chip_id() {
+        assert_eq!(chips(&[(0x77, 0x55)]), vec![(0x77, Chip::Bmp180)]);
+        assert_eq!(chips(&[(0x76, 0x42)]), vec![(0x76, Chip::Unknown)]);
+    }
+
+    #[test]
+    fn names_the_likely_chip_of_an_unknown_device() {
+        let found = scan_bus(&mut MockBus::new(&[(0x3C, 0x00)]), 1);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].chip, Chip::Unknown);
+        assert_eq!(found[0].to_string(), "i2c-1 0x3c: unknown, possibly SSD1306/SH1106 OLED");
+    }
+
+    #[test]
+    fn empty_bus_finds_nothing() {
+        assert!(chips(&[]).is_empty());
+        //reserved addresses are not probed
+        assert!(chips(&[(0x03, 0x00), (0x78, 0x00)]).is_empty());
+    }
+}
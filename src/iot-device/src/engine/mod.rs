@@ -1,75 +1,416 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-pub mod spidisplay;
+use self::sensors::{Quantity, Reading};
+
+pub mod config;
+pub mod display;
 pub mod net_connector;
+#[allow(clippy::module_inception)]
 pub mod engine;
+pub mod hardware;
+pub mod i2c_bus;
+pub mod i2c_scan;
+pub mod health;
+pub mod history;
+pub mod queue;
+pub mod recorder;
+pub mod sensors;
 
 
+/// Last value of a single measurement with its freshness.
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
-pub struct ResultTable {
-    pub dht22_temp: f32, //celsius
-    pub dht22_humidity: f32, //hum %
+pub struct Measured {
+    pub value: f32,
+    //None until the first successful read
+    pub last_success: Option<Instant>,
+    //reads failed since last_success, 0 when the last read succeeded
+    pub failures: u32,
+}
+
+impl Measured {
+    /// The value when the last read succeeded and is not older than `stale_after`.
+    pub fn available(&self, stale_after: Duration) -> Option<f32> {
+        match self.last_success {
+            Some(last_success) if self.failures == 0 && last_success.elapsed() <= stale_after => {
+                Some(self.value)
+            }
+            _ => None,
+        }
+    }
 
-    pub aht20_temp: f32, //celsius
-    pub aht20_humidity: f32, //hum %
+    fn state(&self, stale_after: Duration) -> &'static str {
+        match self.last_success {
+            None if self.failures == 0 => "waiting",
+            _ if self.failures > 0 => "failed",
+            Some(last_success) if last_success.elapsed() > stale_after => "stale",
+            _ => "ok",
+        }
+    }
+}
+
+/// One measurement column of `ResultTable`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub sensor_id: String,
+    pub quantity: Quantity,
+    //key in recorded traces, see column_name
+    pub name: String,
+    pub measured: Measured,
+}
 
-    pub bmp280_temp: f32, //celsius
-    pub bmp280_pressure: f32, //kpa
+/// Latest value of every (sensor, quantity) pair the device measures.
+///
+/// Columns are registered from `Sensor::quantities` when the engine starts, readings of a
+/// pair that was not registered add a new column at the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultTable {
+    //in registration order
+    columns: Vec<Column>,
 
     pub demo_switch: bool,
+
+    //readings older than this are treated as unavailable
+    pub stale_after: Duration,
 }
 
-pub struct EnterTimerGuard {
-    interval: Duration,
-    last_enter: Instant,
-    state: EnterTimerGuardState,
+impl Default for ResultTable {
+    fn default() -> Self {
+        ResultTable {
+            columns: Vec::new(),
+            demo_switch: false,
+            stale_after: Duration::from_secs(30),
+        }
+    }
 }
 
-#[derive(PartialEq, Eq)]
-enum EnterTimerGuardState {
-    Default,
-    ForceNextEnter,
+/// Name of a measurement column, e.g. "aht20_temp" or "bmp280_pressure".
+pub fn column_name(sensor_id: &str, quantity: Quantity) -> String {
+    format!("{}_{}", sensor_id, quantity.key())
 }
 
-impl EnterTimerGuard {
-    pub fn new(interval: Duration) -> EnterTimerGuard {
-        let last_enter = Instant::now();
-        let state = EnterTimerGuardState::Default;
-        EnterTimerGuard {
-            interval,
-            last_enter,
-            state
+/// Inverse of `column_name`, None when the name does not end with a quantity key.
+pub fn parse_column_name(name: &str) -> Option<(&str, Quantity)> {
+    let (sensor_id, key) = name.rsplit_once('_')?;
+    if sensor_id.is_empty() {
+        return None;
+    }
+    Some((sensor_id, Quantity::from_key(key)?))
+}
+
+impl ResultTable {
+    /// Adds the columns of a sensor, pairs that already have a column are kept as they are.
+    pub fn register(&mut self, sensor_id: &str, quantities: &[Quantity]) {
+        for quantity in quantities {
+            self.column_mut(sensor_id, *quantity);
         }
     }
 
-    pub fn enter(&mut self) -> bool {
-        if (self.last_enter.elapsed() > self.interval) || (self.state == EnterTimerGuardState::ForceNextEnter) {
-            self.last_enter = Instant::now();
-            self.state = EnterTimerGuardState::Default;
-            return true;
+    /// Stores readings of the sensor identified by `sensor_id`.
+    ///
+    /// Columns of the sensor missing from `readings` count as failed, so a channel failing a partial
+    /// read is unavailable right away instead of once it goes stale.
+    pub fn apply(&mut self, sensor_id: &str, readings: &[Reading]) {
+        let now = Instant::now();
+        for reading in readings {
+            self.column_mut(sensor_id, reading.quantity).measured = Measured {
+                value: reading.value,
+                last_success: Some(now),
+                failures: 0,
+            };
+        }
+        for column in self.columns.iter_mut().filter(|it| it.sensor_id == sensor_id) {
+            if !readings.iter().any(|it| it.quantity == column.quantity) {
+                column.measured.failures += 1;
+            }
         }
-        false
     }
-    pub fn force_next_enter(&mut self) {
-        self.state = EnterTimerGuardState::ForceNextEnter;
+
+    /// Marks every measurement of the sensor as failed, the old value is kept but no longer available.
+    pub fn mark_failed(&mut self, sensor_id: &str) {
+        for column in self.columns.iter_mut().filter(|it| it.sensor_id == sensor_id) {
+            column.measured.failures += 1;
+        }
     }
-}
 
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Available value of a measurement column by its name, e.g. "aht20_temp".
+    pub fn get(&self, column: &str) -> Option<f32> {
+        self.measured(column)?.available(self.stale_after)
+    }
+
+    pub fn measured(&self, column: &str) -> Option<&Measured> {
+        self.columns
+            .iter()
+            .find(|it| it.name == column)
+            .map(|it| &it.measured)
+    }
+
+    /// Mean of the available values of a quantity over the columns accepted by `include`.
+    pub fn average(&self, quantity: Quantity, include: impl Fn(&Column) -> bool) -> Option<f32> {
+        let values: Vec<f32> = self
+            .columns
+            .iter()
+            .filter(|it| it.quantity == quantity && include(it))
+            .filter_map(|it| it.measured.available(self.stale_after))
+            .collect();
+
+        if values.is_empty() {
+            return None;
+        }
+        Some(values.iter().sum::<f32>() / values.len() as f32)
+    }
+
+    /// Mean over the air sensors, DS18B20 probes measure water or surfaces and stay out of it.
+    pub fn air_average(&self, quantity: Quantity) -> Option<f32> {
+        self.average(quantity, |column| !sensors::ds18b20::is_probe(&column.sensor_id))
+    }
 
+    /// First available value of the given columns.
+    pub fn first_available(&self, columns: &[&str]) -> Option<f32> {
+        columns.iter().find_map(|column| self.get(column))
+    }
+
+    /// First available value of a quantity in column order.
+    pub fn first_available_of(&self, quantity: Quantity) -> Option<f32> {
+        self.columns
+            .iter()
+            .filter(|it| it.quantity == quantity)
+            .find_map(|it| it.measured.available(self.stale_after))
+    }
+
+    fn column_mut(&mut self, sensor_id: &str, quantity: Quantity) -> &mut Column {
+        let index = match self
+            .columns
+            .iter()
+            .position(|it| it.sensor_id == sensor_id && it.quantity == quantity)
+        {
+            Some(index) => index,
+            None => {
+                self.columns.push(Column {
+                    sensor_id: sensor_id.to_string(),
+                    quantity,
+                    name: column_name(sensor_id, quantity),
+                    measured: Measured::default(),
+                });
+                self.columns.len() - 1
+            }
+        };
+        &mut self.columns[index]
+    }
+}
+
+impl fmt::Display for ResultTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for column in self.columns.iter() {
+            match column.measured.available(self.stale_after) {
+                Some(value) => write!(f, "{}: {:.2}, ", column.name, value)?,
+                None => write!(
+                    f,
+                    "{}: n/a ({}), ",
+                    column.name,
+                    column.measured.state(self.stale_after)
+                )?,
+            }
+        }
+        write!(f, "demo_switch: {}", self.demo_switch)
+    }
+}
 
 #[derive(Parser, Debug, Clone)]
 pub struct ProgramArgs {
-    #[arg(short, long, default_value = "air")]
-    pub id_device: String,
-    #[arg(long, default_value = "localhost")]
-    pub host_mqqt: String,
-    #[arg(short, long, default_value_t = 1883)]
-    pub port_mqqt: u16,
+    /// TOML configuration file, flags below override its values
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    #[arg(short, long)]
+    pub id_device: Option<String>,
+    #[arg(long)]
+    pub host_mqqt: Option<String>,
+    #[arg(short, long)]
+    pub port_mqqt: Option<u16>,
 
     #[arg(long)]
     pub username_mqqt: Option<String>,
     #[arg(long)]
     pub password_mqqt: Option<String>,
-}
\ No newline at end of file
+
+    /// Connect to the broker over TLS
+    #[arg(long)]
+    pub tls: bool,
+    /// CA bundle (PEM) used to verify the broker, implies --tls; the system certificates when not given
+    #[arg(long)]
+    pub ca_file: Option<PathBuf>,
+    /// Client certificate (PEM) for mutual TLS
+    #[arg(long, requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+    /// Client private key (PEM) for mutual TLS
+    #[arg(long, requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+    /// Name expected in the broker certificate when it differs from the host
+    #[arg(long)]
+    pub tls_server_name: Option<String>,
+    /// Accept any broker certificate, for testing only
+    #[arg(long)]
+    pub tls_insecure: bool,
+
+    /// Replace the board sensors with synthetic generators
+    #[arg(long)]
+    pub simulate: bool,
+    /// Seed of the simulated sensors, derived from id_device when not set
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Probability (0.0-1.0) that a simulated read starts a dropout, simulation.dropout_rate when not set
+    #[arg(long)]
+    pub dropout_rate: Option<f64>,
+
+    /// Replay sensor readings from a recorded .csv or .jsonl trace, the device stops at its end
+    #[arg(long, conflicts_with = "simulate")]
+    pub replay: Option<PathBuf>,
+    /// Replay speed multiplier, 1.0 keeps the original timing
+    #[arg(long, default_value_t = 1.0)]
+    pub replay_speed: f32,
+
+    /// Append every sample to a .csv or .jsonl trace file
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// Size of the trace file after which it is rotated
+    #[arg(long)]
+    pub record_max_bytes: Option<u64>,
+    /// Number of rotated trace files to keep
+    #[arg(long)]
+    pub record_keep: Option<u32>,
+
+    /// Build the I2C sensors from the chips found on sensors.i2c_buses
+    #[arg(long)]
+    pub autodetect: bool,
+
+    #[command(subcommand)]
+    pub command: Option<DeviceCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DeviceCommand {
+    /// Probe the I2C buses, list the IIO devices and the DS18B20 probes, then exit
+    Scan {
+        /// Bus number to probe, repeatable; sensors.i2c_buses when not given
+        #[arg(long)]
+        bus: Vec<u8>,
+    },
+}
+
+impl ProgramArgs {
+    pub fn simulation_seed(&self, id_device: &str) -> u64 {
+        self.seed.unwrap_or_else(|| {
+            let mut hasher = DefaultHasher::new();
+            id_device.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn both(temperature: f32, humidity: f32) -> [Reading; 2] {
+        [
+            Reading::new(Quantity::Temperature, temperature),
+            Reading::new(Quantity::Humidity, humidity),
+        ]
+    }
+
+    #[test]
+    fn parses_column_names() {
+        assert_eq!(parse_column_name("aht20_temp"), Some(("aht20", Quantity::Temperature)));
+        assert_eq!(parse_column_name("bmp280_pressure"), Some(("bmp280", Quantity::Pressure)));
+        //sensor ids may contain underscores, the quantity is after the last one
+        assert_eq!(
+            parse_column_name("iio_bme_humidity"),
+            Some(("iio_bme", Quantity::Humidity))
+        );
+        assert_eq!(parse_column_name("aht20_voltage"), None);
+        assert_eq!(parse_column_name("_temp"), None);
+        assert_eq!(parse_column_name("temp"), None);
+
+        let name = column_name("28-0316a2791aff", Quantity::Temperature);
+        assert_eq!(parse_column_name(&name), Some(("28-0316a2791aff", Quantity::Temperature)));
+    }
+
+    #[test]
+    fn register_keeps_order_and_existing_columns() {
+        let mut table = ResultTable::default();
+        table.register("aht20", &[Quantity::Temperature, Quantity::Humidity]);
+        table.apply("aht20", &both(21.5, 40.0));
+        table.register("bmp280", &[Quantity::Temperature, Quantity::Pressure]);
+        table.register("aht20", &[Quantity::Temperature]);
+
+        let names: Vec<&str> = table.columns().iter().map(|it| it.name.as_str()).collect();
+        assert_eq!(names, ["aht20_temp", "aht20_humidity", "bmp280_temp", "bmp280_pressure"]);
+        assert_eq!(table.get("aht20_temp"), Some(21.5));
+        //registered but never read
+        assert_eq!(table.get("bmp280_temp"), None);
+        assert_eq!(table.measured("bmp280_temp").unwrap().state(table.stale_after), "waiting");
+    }
+
+    #[test]
+    fn apply_adds_unregistered_columns() {
+        let mut table = ResultTable::default();
+        table.apply("sim", &[Reading::new(Quantity::Pressure, 101.3)]);
+        assert_eq!(table.columns().len(), 1);
+        assert_eq!(table.get("sim_pressure"), Some(101.3));
+    }
+
+    #[test]
+    fn partial_read_fails_the_missing_columns() {
+        let mut table = ResultTable::default();
+        table.register("dht22", &[Quantity::Temperature, Quantity::Humidity]);
+        table.apply("dht22", &both(20.0, 50.0));
+        table.apply("dht22", &[Reading::new(Quantity::Temperature, 20.5)]);
+
+        assert_eq!(table.get("dht22_temp"), Some(20.5));
+        assert_eq!(table.get("dht22_humidity"), None);
+        assert_eq!(table.measured("dht22_humidity").unwrap().state(table.stale_after), "failed");
+
+        //the next complete read recovers it
+        table.apply("dht22", &both(20.5, 51.0));
+        assert_eq!(table.get("dht22_humidity"), Some(51.0));
+    }
+
+    #[test]
+    fn mark_failed_keeps_the_value_but_hides_it() {
+        let mut table = ResultTable::default();
+        table.apply("aht20", &[Reading::new(Quantity::Temperature, 22.0)]);
+        table.apply("bmp280", &[Reading::new(Quantity::Temperature, 23.0)]);
+        table.mark_failed("aht20");
+        table.mark_failed("aht20");
+
+        let measured = table.measured("aht20_temp").unwrap();
+        assert_eq!(measured.value, 22.0);
+        assert_eq!(measured.failures, 2);
+        assert_eq!(table.get("aht20_temp"), None);
+        assert_eq!(table.get("bmp280_temp"), Some(23.0));
+        assert_eq!(table.first_available_of(Quantity::Temperature), Some(23.0));
+    }
+
+    #[test]
+    fn stale_values_are_unavailable() {
+        let mut table = ResultTable {
+            stale_after: Duration::ZERO,
+            ..ResultTable::default()
+        };
+        table.apply("aht20", &[Reading::new(Quantity::Temperature, 22.0)]);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(table.get("aht20_temp"), None);
+        assert_eq!(table.measured("aht20_temp").unwrap().state(table.stale_after), "stale");
+    }
+}
@@ -1,36 +1,88 @@
 use std::{
-    fs,
-    sync::Arc,
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     time::{Duration, SystemTime},
 };
 
-use clap::builder::Str;
+use mqtt_tls::TlsSettings;
 use prost::Message;
-use prost_types::Timestamp;
 use rumqttc::{
-    AsyncClient, ConnAck, ConnectReturnCode, Event, EventLoop, Incoming, MqttOptions, Packet,
-    PubAck, QoS, TlsConfiguration, Transport,
+    AsyncClient, ConnAck, ConnectReturnCode, ConnectionError, Event, Incoming, LastWill,
+    MqttOptions, Outgoing, Packet, PubAck, QoS, Transport,
 };
 use tokio::{
-    sync::mpsc::Receiver,
+    sync::{
+        mpsc::{self, Sender, UnboundedReceiver},
+        watch, Notify,
+    },
     task::{self, JoinHandle},
 };
 
-use crate::proto::proto_broker_msgs::{self, ServerMessage};
+use crate::error::{DeviceError, DeviceResult};
+use crate::proto::proto_broker_msgs::{self, presence_message, sensor_reading, ServerMessage};
 
-use super::{ProgramArgs, ResultTable};
+use super::{
+    config::{DeviceConfig, TopicsConfig},
+    health::{ReadCounters, RefreshCounters, SystemStats},
+    queue::TelemetryQueue,
+    sensors::Quantity,
+    ResultTable,
+};
+
+//how long a queued message may wait for PubAck before it is published again
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+//how long shutdown waits for each of the offline presence and Disconnect requests
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+//QoS 1 delivery progress reported by the event loop to the queue drain task
+#[derive(Debug, Clone, Copy)]
+enum Delivery {
+    Sent(u16),
+    Acked(u16),
+}
 
 pub struct NetConnector {
     thread_handle: JoinHandle<()>,
+    drain_handle: Option<JoinHandle<()>>,
+    heartbeat_handle: Option<JoinHandle<()>>,
     pub client: AsyncClient,
-    pub receiver: Receiver<ServerMessage>,
     settings: NetConnectorSettings,
+    queue: Option<Arc<Mutex<TelemetryQueue>>>,
+    queue_notify: Arc<Notify>,
+    //successful ConnAcks since start
+    connections: Arc<AtomicU32>,
+    //reported as optional_state of the heartbeat
+    demo_switch: Arc<AtomicBool>,
+    connected: watch::Receiver<bool>,
+    //PubAck of the last telemetry message, the only QoS 1 publish
+    last_delivery: Arc<Mutex<Option<SystemTime>>>,
 }
 
 impl NetConnector {
-    pub async fn start_thread(settings: NetConnectorSettings) -> NetConnector {
+    /// Connects in the background, server commands are forwarded to `commands`.
+    ///
+    /// When the telemetry queue cannot be opened telemetry is published directly, so it is lost
+    /// while the broker is unreachable.
+    pub async fn start_thread(
+        settings: NetConnectorSettings,
+        commands: Sender<ServerMessage>,
+    ) -> NetConnector {
         println!("Start thread, args: {:?}", settings);
 
+        let queue = settings.queue_dir.as_ref().and_then(|dir| {
+            match TelemetryQueue::open(dir, settings.queue_max_messages) {
+                Ok(queue) => Some(Arc::new(Mutex::new(queue))),
+                Err(err) => {
+                    println!("Telemetry queue disabled, telemetry is lost while offline: {}", err);
+                    None
+                }
+            }
+        });
+
         let mut mqttoptions = MqttOptions::new(
             settings.id_device.clone(),
             settings.host.clone(),
@@ -39,23 +91,51 @@ impl NetConnector {
 
         mqttoptions.set_credentials(settings.username.clone(), settings.password.clone());
         mqttoptions
-            .set_keep_alive(Duration::from_secs(5))
+            .set_keep_alive(settings.keep_alive)
             .set_pending_throttle(Duration::from_secs(2));
+        //the broker publishes this when the connection dies without a clean disconnect, e.g. on power loss
+        mqttoptions.set_last_will(LastWill::new(
+            settings.topics.presence.clone(),
+            presence_payload(&settings.id_device, presence_message::State::ConnectionLost),
+            QoS::AtLeastOnce,
+            true,
+        ));
 
-        let (client, mut connection) = AsyncClient::new(mqttoptions, 0);
-        let (ts, receiver) = tokio::sync::mpsc::channel::<ServerMessage>(5);
+        if let Some(tls) = settings.tls.as_ref() {
+            //the files were checked by DeviceConfig::validate, a failure here means they changed since
+            match tls.load() {
+                Ok(tls_config) => {
+                    mqttoptions.set_transport(Transport::Tls(tls_config));
+                }
+                Err(err) => println!("{}, the connection will fail", err),
+            }
+        }
+
+        let (client, mut connection) = AsyncClient::new(mqttoptions, 10);
+        let (connected_tx, connected_rx) = watch::channel(false);
+        let (delivery_tx, delivery_rx) = mpsc::unbounded_channel::<Delivery>();
+        let connections = Arc::new(AtomicU32::new(0));
+        let last_delivery = Arc::new(Mutex::new(None));
 
         let move_client = client.clone();
         let move_settings = settings.clone();
+        let move_connections = connections.clone();
+        let move_last_delivery = last_delivery.clone();
         let thread_handle = tokio::spawn(async move {
             let client = move_client;
             let settings = move_settings;
-            let sender = ts;
+            let connections = move_connections;
+            let last_delivery = move_last_delivery;
+            let sender = commands;
             loop {
                 let notification = connection.poll().await;
                 println!("Notification: {:?}", notification);
                 match notification {
-                    Err(_) => {
+                    Err(err) => {
+                        if let ConnectionError::Tls(tls_err) = &err {
+                            println!("TLS connection to {}:{} failed: {}", settings.host, settings.port, tls_err);
+                        }
+                        let _ = connected_tx.send(false);
                         tokio::time::sleep(Duration::from_secs(5)).await;
                         continue;
                     }
@@ -64,8 +144,31 @@ impl NetConnector {
                         session_present: false,
                         code: ConnectReturnCode::Success,
                     }))) => {
+                        connections.fetch_add(1, Ordering::Relaxed);
+                        publish_presence(&client, &settings, presence_message::State::Online);
+                        let _ = connected_tx.send(true);
                         //register subscribe, because there is no existing session
-                        register_subscribe(client.clone(), settings.id_device.clone());
+                        register_subscribe(client.clone(), settings.topics.clone());
+                    }
+                    Ok(Event::Incoming(Packet::ConnAck(ConnAck {
+                        code: ConnectReturnCode::Success,
+                        ..
+                    }))) => {
+                        connections.fetch_add(1, Ordering::Relaxed);
+                        publish_presence(&client, &settings, presence_message::State::Online);
+                        let _ = connected_tx.send(true);
+                    }
+                    Ok(Event::Incoming(Incoming::PubAck(PubAck { pkid, .. }))) => {
+                        *last_delivery.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(SystemTime::now());
+                        let _ = delivery_tx.send(Delivery::Acked(pkid));
+                    }
+                    Ok(Event::Outgoing(Outgoing::Publish(pkid))) if pkid != 0 => {
+                        let _ = delivery_tx.send(Delivery::Sent(pkid));
+                    }
+                    //requested by shutdown, everything queued before it has been written
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                        let _ = connected_tx.send(false);
+                        break;
                     }
                     Ok(Event::Incoming(Incoming::Publish(packet))) => {
                         println!("Incoming message!");
@@ -75,10 +178,9 @@ impl NetConnector {
                             proto_broker_msgs::ServerMessage::decode(packet.payload.clone())
                         {
                             println!("ServerMessage: {:?}", res);
-                            sender
-                                .send_timeout(res, Duration::from_secs(5))
-                                .await
-                                .unwrap();
+                            if sender.send_timeout(res, Duration::from_secs(5)).await.is_err() {
+                                println!("Engine does not take commands, dropping command");
+                            }
                         }
                     }
                     _ => (),
@@ -88,27 +190,101 @@ impl NetConnector {
 
         //register_subscribe(client.clone(), settings.id_device.clone());
 
+        let demo_switch = Arc::new(AtomicBool::new(false));
+        let heartbeat_handle = settings.heartbeat_interval.map(|interval| {
+            tokio::spawn(heartbeat(
+                client.clone(),
+                settings.id_device.clone(),
+                settings.topics.activity.clone(),
+                interval,
+                demo_switch.clone(),
+                connected_rx.clone(),
+            ))
+        });
+        let connected = connected_rx.clone();
+
+        let queue_notify = Arc::new(Notify::new());
+        let drain_handle = queue.as_ref().map(|queue| {
+            tokio::spawn(drain_queue(
+                client.clone(),
+                settings.topics.telemetry.clone(),
+                queue.clone(),
+                queue_notify.clone(),
+                connected_rx,
+                delivery_rx,
+            ))
+        });
+
         NetConnector {
             thread_handle,
+            drain_handle,
+            heartbeat_handle,
             client,
-            receiver,
             settings,
+            queue,
+            queue_notify,
+            connections,
+            demo_switch,
+            connected,
+            last_delivery,
         }
     }
 
+    pub fn is_connected(&self) -> bool {
+        *self.connected.borrow()
+    }
+
+    /// When the broker last acknowledged a telemetry message.
+    pub fn last_delivery(&self) -> Option<SystemTime> {
+        *self.last_delivery.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// State sent with the next heartbeat.
+    pub fn set_demo_switch(&self, state: bool) {
+        self.demo_switch.store(state, Ordering::Relaxed);
+    }
+
     pub async fn send_data(&self, result_table: ResultTable) {
+        let readings = sensor_readings(&result_table);
+        if readings.is_empty() {
+            println!("Telemetry skipped, no valid reading: {}", result_table);
+            return;
+        }
+        //the legacy fields prefer the original sensors, a quantity nothing measured stays unset
+        let temperature = result_table
+            .first_available(&["aht20_temp", "dht22_temp", "bmp280_temp"])
+            .or_else(|| result_table.first_available_of(Quantity::Temperature));
+        let humidity = result_table
+            .first_available(&["aht20_humidity", "dht22_humidity"])
+            .or_else(|| result_table.first_available_of(Quantity::Humidity));
+        let pressure = result_table
+            .first_available(&["bmp280_pressure"])
+            .or_else(|| result_table.first_available_of(Quantity::Pressure));
+
         println!("Sending data via MQTT...\n");
 
         let message = proto_broker_msgs::TelemetryMessage {
             id_device: self.settings.id_device.clone(),
-            humidity: result_table.aht20_humidity,
-            pressure: result_table.bmp280_pressure,
-            temperature: result_table.aht20_temp,
+            humidity,
+            pressure,
+            temperature,
             timestamp: Some(SystemTime::now().into()),
+            readings,
         };
         let body = message.encode_to_vec();
-        let topic = format!("iotserver/{}/sendtelemetry", self.settings.id_device);
 
+        if let Some(queue) = self.queue.as_ref() {
+            let pushed = lock_queue(queue).push(&body);
+            match pushed {
+                Ok(()) => {
+                    self.queue_notify.notify_one();
+                    return;
+                }
+                Err(error) => println!("Telemetry queue push error: {}", error),
+            }
+        }
+
+        let topic = self.settings.topics.telemetry.clone();
         let publish_result = self
             .client
             .try_publish(topic, QoS::AtLeastOnce, false, body);
@@ -118,34 +294,335 @@ impl NetConnector {
         }
     }
 
+    /// Number of telemetry messages waiting for the broker.
+    pub fn queued(&self) -> usize {
+        self.queue
+            .as_ref()
+            .map(|queue| lock_queue(queue).len())
+            .unwrap_or(0)
+    }
+
+    /// Publishes a `HealthMessage`, QoS 0 so it never takes a packet id the queue drain waits for.
+    pub fn send_health(
+        &self,
+        uptime: Duration,
+        sensors: &[(&str, ReadCounters)],
+        stats: SystemStats,
+        display: Option<RefreshCounters>,
+    ) {
+        let [load_average_1m, load_average_5m, load_average_15m] = match stats.load_average {
+            Some([one, five, fifteen]) => [Some(one), Some(five), Some(fifteen)],
+            None => [None; 3],
+        };
+        let message = proto_broker_msgs::HealthMessage {
+            id_device: self.settings.id_device.clone(),
+            timestamp: Some(SystemTime::now().into()),
+            uptime_secs: uptime.as_secs(),
+            sensors: sensors
+                .iter()
+                .map(|(sensor_id, counters)| proto_broker_msgs::SensorHealth {
+                    sensor_id: sensor_id.to_string(),
+                    read_successes: counters.successes,
+                    read_failures: counters.failures,
+                })
+                .collect(),
+            mqtt_reconnects: self.reconnects(),
+            queued_messages: self.queued() as u32,
+            soc_temperature: stats.soc_temperature,
+            load_average_1m,
+            load_average_5m,
+            load_average_15m,
+            memory_available_bytes: stats.memory_available_bytes,
+            memory_total_bytes: stats.memory_total_bytes,
+            display: display.map(|counters| proto_broker_msgs::DisplayRefreshes {
+                full: counters.full,
+                partial: counters.partial,
+                skipped: counters.skipped,
+                failed: counters.failed,
+            }),
+        };
+
+        let topic = self.settings.topics.health.clone();
+        if let Err(error) = self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, false, message.encode_to_vec())
+        {
+            println!("Health publish error: {:?}", error);
+        }
+    }
+
+    /// Reconnects after the first successful connection.
+    pub fn reconnects(&self) -> u32 {
+        self.connections.load(Ordering::Relaxed).saturating_sub(1)
+    }
+
+    /// Replaces the retained presence with Offline and disconnects cleanly, so the broker discards the last will.
+    /// Each step gives up after `DISCONNECT_TIMEOUT`, the request channel stays full while the broker is down.
+    pub async fn disconnect(&self) -> DeviceResult<()> {
+        let payload = presence_payload(&self.settings.id_device, presence_message::State::Offline);
+        let publish = self
+            .client
+            .publish(self.settings.topics.presence.clone(), QoS::AtMostOnce, true, payload);
+        tokio::time::timeout(DISCONNECT_TIMEOUT, publish)
+            .await
+            .map_err(|_| DeviceError::Timeout {
+                device: "MQTT publish offline presence".to_string(),
+                after: DISCONNECT_TIMEOUT,
+            })?
+            .map_err(|source| DeviceError::Mqtt {
+                context: "publish offline presence".to_string(),
+                source,
+            })?;
+        tokio::time::timeout(DISCONNECT_TIMEOUT, self.client.disconnect())
+            .await
+            .map_err(|_| DeviceError::Timeout {
+                device: "MQTT disconnect".to_string(),
+                after: DISCONNECT_TIMEOUT,
+            })?
+            .map_err(|source| DeviceError::Mqtt {
+                context: "disconnect".to_string(),
+                source,
+            })
+    }
+
+    /// Gives queued telemetry up to `flush_timeout` to reach the broker, then goes offline and ends the connection.
+    pub async fn shutdown(mut self, flush_timeout: Duration) {
+        let connected = *self.connected.borrow();
+        if connected && tokio::time::timeout(flush_timeout, self.flushed()).await.is_err() {
+            println!("Shutdown: flush timed out");
+        }
+        if self.queued() > 0 {
+            println!("Shutdown: {} telemetry messages stay queued for the next start", self.queued());
+        }
+
+        match self.disconnect().await {
+            Ok(()) => {
+                //the event loop ends once Disconnect is written
+                let _ = tokio::time::timeout(Duration::from_secs(2), &mut self.thread_handle).await;
+            }
+            Err(err) => println!("Shutdown: giving up on a clean disconnect, {}", err),
+        }
+        self.stop();
+    }
+
+    async fn flushed(&self) {
+        while self.queued() > 0 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
     pub fn stop(self) {
         println!("Aborting net_connector");
+        if let Some(drain_handle) = self.drain_handle {
+            drain_handle.abort();
+        }
+        if let Some(heartbeat_handle) = self.heartbeat_handle {
+            heartbeat_handle.abort();
+        }
         self.thread_handle.abort();
         println!("Aborted net_connector");
     }
 }
 
+//every measurement of the table, the legacy fields above carry only aht20 and bmp280 pressure
+fn sensor_readings(result_table: &ResultTable) -> Vec<proto_broker_msgs::SensorReading> {
+    result_table
+        .columns()
+        .iter()
+        .filter_map(|column| {
+            let mut reading = proto_broker_msgs::SensorReading {
+                sensor_id: column.sensor_id.clone(),
+                unit: column.quantity.unit().to_string(),
+                value: column.measured.available(result_table.stale_after)?,
+                ..Default::default()
+            };
+            reading.set_quantity(proto_quantity(column.quantity));
+            Some(reading)
+        })
+        .collect()
+}
+
+fn proto_quantity(quantity: Quantity) -> sensor_reading::Quantity {
+    match quantity {
+        Quantity::Temperature => sensor_reading::Quantity::Temperature,
+        Quantity::Humidity => sensor_reading::Quantity::Humidity,
+        Quantity::Pressure => sensor_reading::Quantity::Pressure,
+    }
+}
+
+fn presence_payload(id_device: &str, state: presence_message::State) -> Vec<u8> {
+    let mut message = proto_broker_msgs::PresenceMessage {
+        id_device: id_device.to_string(),
+        timestamp: Some(SystemTime::now().into()),
+        ..Default::default()
+    };
+    message.set_state(state);
+    message.encode_to_vec()
+}
+
+//retained QoS 0, a QoS 1 publish here could take the packet id the queue drain is waiting for;
+//if it is lost the connection is gone and the last will replaces it anyway
+fn publish_presence(client: &AsyncClient, settings: &NetConnectorSettings, state: presence_message::State) {
+    let payload = presence_payload(&settings.id_device, state);
+    if let Err(error) = client.try_publish(settings.topics.presence.clone(), QoS::AtMostOnce, true, payload) {
+        println!("Presence publish error: {:?}", error);
+    }
+}
+
+//ActivityMesssage every interval while connected, QoS 0 like the kditool simulator
+async fn heartbeat(
+    client: AsyncClient,
+    id_device: String,
+    topic: String,
+    interval: Duration,
+    demo_switch: Arc<AtomicBool>,
+    mut connected: watch::Receiver<bool>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        //while offline the request channel would only fill up with stale heartbeats
+        while !*connected.borrow_and_update() {
+            if connected.changed().await.is_err() {
+                return;
+            }
+        }
+
+        let payload = proto_broker_msgs::ActivityMesssage {
+            id_device: id_device.clone(),
+            optional_state: demo_switch.load(Ordering::Relaxed),
+        }
+        .encode_to_vec();
+        if let Err(error) = client.try_publish(topic.clone(), QoS::AtMostOnce, false, payload) {
+            println!("Heartbeat publish error: {:?}", error);
+        }
+    }
+}
+
+//publishes queued telemetry oldest first, a message is removed only after the broker acknowledged it
+async fn drain_queue(
+    client: AsyncClient,
+    topic: String,
+    queue: Arc<Mutex<TelemetryQueue>>,
+    queue_notify: Arc<Notify>,
+    mut connected: watch::Receiver<bool>,
+    mut deliveries: UnboundedReceiver<Delivery>,
+) {
+    loop {
+        while !*connected.borrow_and_update() {
+            if connected.changed().await.is_err() {
+                return;
+            }
+        }
+
+        let next = lock_queue(&queue).peek();
+        let (sequence, payload) = match next {
+            Ok(Some(next)) => next,
+            Ok(None) => {
+                queue_notify.notified().await;
+                continue;
+            }
+            Err(error) => {
+                println!("Telemetry queue read error: {}", error);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        //forget deliveries of earlier attempts
+        while deliveries.try_recv().is_ok() {}
+
+        if let Err(error) = client.publish(topic.clone(), QoS::AtLeastOnce, false, payload).await {
+            println!("Queued telemetry publish error: {:?}", error);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+
+        match tokio::time::timeout(ACK_TIMEOUT, wait_for_ack(&mut deliveries)).await {
+            Ok(true) => {
+                let removed = lock_queue(&queue).remove(sequence);
+                if let Err(error) = removed {
+                    println!("Telemetry queue remove error: {}", error);
+                }
+            }
+            Ok(false) => return,
+            Err(_) => println!("Telemetry {} not acknowledged, retrying", sequence),
+        }
+    }
+}
+
+//a panic while holding the lock leaves the files on disk consistent, so the queue stays usable
+fn lock_queue(queue: &Mutex<TelemetryQueue>) -> MutexGuard<'_, TelemetryQueue> {
+    queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+//the drain task is the only QoS 1 publisher, so the first sent packet id after publish is ours
+async fn wait_for_ack(deliveries: &mut UnboundedReceiver<Delivery>) -> bool {
+    let mut pkid = None;
+    while let Some(delivery) = deliveries.recv().await {
+        match delivery {
+            Delivery::Sent(sent) if pkid.is_none() => pkid = Some(sent),
+            Delivery::Acked(acked) if Some(acked) == pkid => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
 //in the new task because it can block if the inner receiver is full, making it a problem if this function has been used in the main loop
-fn register_subscribe(client: AsyncClient, id_device: String) {
+fn register_subscribe(client: AsyncClient, topics: TopicsConfig) {
     task::spawn(async move {
-        client
-            .subscribe(format!("iot/{}/receive", id_device), QoS::AtMostOnce)
-            .await
-            .unwrap();
-        client
-            .subscribe("iot/global", QoS::AtMostOnce)
-            .await
-            .unwrap();
+        for topic in [topics.receive, topics.global] {
+            //fails only when the event loop is gone, the next ConnAck subscribes again
+            if let Err(error) = client.subscribe(topic.clone(), QoS::AtMostOnce).await {
+                println!("Subscribe to {} failed: {:?}", topic, error);
+                return;
+            }
+        }
     });
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct NetConnectorSettings {
     pub id_device: String,
     pub host: String,
     pub port: u16,
     pub username: String,
     pub password: String,
+    pub keep_alive: Duration,
+    //topics with the device id already substituted
+    pub topics: TopicsConfig,
+    //store-and-forward queue directory, None publishes directly
+    pub queue_dir: Option<PathBuf>,
+    pub queue_max_messages: usize,
+    //None connects over plain TCP
+    pub tls: Option<TlsSettings>,
+    //None disables the heartbeat
+    pub heartbeat_interval: Option<Duration>,
+}
+
+//the settings are logged at startup, so the password and the client key path stay out
+impl fmt::Debug for NetConnectorSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tls = self.tls.as_ref().map(|tls| TlsSettings {
+            client_key_file: tls.client_key_file.as_ref().map(|_| "<redacted>".into()),
+            ..tls.clone()
+        });
+        f.debug_struct("NetConnectorSettings")
+            .field("id_device", &self.id_device)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("keep_alive", &self.keep_alive)
+            .field("topics", &self.topics)
+            .field("queue_dir", &self.queue_dir)
+            .field("queue_max_messages", &self.queue_max_messages)
+            .field("tls", &tls)
+            .field("heartbeat_interval", &self.heartbeat_interval)
+            .finish()
+    }
 }
 
 impl NetConnectorSettings {
@@ -156,24 +633,64 @@ impl NetConnectorSettings {
         username: String,
         password: String,
     ) -> NetConnectorSettings {
+        let topics = TopicsConfig::default().resolve(&id_device);
         NetConnectorSettings {
             id_device,
             host,
             port,
             username,
             password,
+            keep_alive: Duration::from_secs(5),
+            topics,
+            queue_dir: None,
+            queue_max_messages: 0,
+            tls: None,
+            heartbeat_interval: Some(Duration::from_secs(10)),
+        }
+    }
+
+    pub fn from_config(config: &DeviceConfig) -> NetConnectorSettings {
+        let broker = &config.broker;
+        NetConnectorSettings {
+            id_device: config.device.id.clone(),
+            host: broker.host.clone(),
+            port: broker.port,
+            username: broker.username.clone(),
+            password: broker.password.clone(),
+            keep_alive: Duration::from_secs(broker.keep_alive_secs),
+            topics: config.topics.resolve(&config.device.id),
+            queue_dir: config.queue.enabled.then(|| config.queue.dir(&config.device.id)),
+            queue_max_messages: config.queue.max_messages,
+            tls: config.broker.tls.settings(),
+            heartbeat_interval: config
+                .heartbeat
+                .enabled
+                .then(|| Duration::from_secs(config.heartbeat.interval_secs)),
         }
     }
 }
 
-#[allow(dead_code)]
-fn set_cert(mut mqttoptions: MqttOptions) -> MqttOptions {
-    let ca: Vec<u8> =
-        fs::read("ca_certificate.pem").expect("Something went wrong reading certificate!");
-    mqttoptions.set_transport(Transport::Tls(TlsConfiguration::Simple {
-        ca: ca,
-        alpn: None,
-        client_auth: None,
-    }));
-    mqttoptions
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_output_hides_the_password_and_the_client_key() {
+        let mut settings = NetConnectorSettings::new(
+            "dev1".into(),
+            "broker.local".into(),
+            8883,
+            "theserver".into(),
+            "myserverpass".into(),
+        );
+        settings.tls = Some(TlsSettings {
+            client_key_file: Some("/etc/kd-iot/client.key".into()),
+            ..Default::default()
+        });
+
+        let printed = format!("{:?}", settings);
+        assert!(!printed.contains("myserverpass"), "{}", printed);
+        assert!(!printed.contains("client.key"), "{}", printed);
+        assert!(printed.contains("broker.local") && printed.contains("8883"), "{}", printed);
+    }
 }
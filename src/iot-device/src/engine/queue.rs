@@ -0,0 +1,227 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{DeviceError, DeviceResult};
+
+//sequence number and encoded message
+type Entry = (u64, Vec<u8>);
+
+/// Bounded on-disk FIFO of encoded messages waiting for the broker.
+///
+/// Every message is a separate `<sequence>.msg` file written through a temporary file and a rename,
+/// so a power loss leaves either the whole message or nothing. When the queue is over `max_messages`
+/// the oldest messages are dropped.
+pub struct TelemetryQueue {
+    dir: PathBuf,
+    max_messages: usize,
+    //sequence numbers of the stored messages, oldest first
+    entries: VecDeque<u64>,
+    next_sequence: u64,
+    dropped: u64,
+}
+
+impl TelemetryQueue {
+    pub fn open(dir: &Path, max_messages: usize) -> DeviceResult<TelemetryQueue> {
+        let context = || format!("open queue directory {}", dir.display());
+        fs::create_dir_all(dir).map_err(|err| DeviceError::io(context(), err))?;
+
+        let mut sequences = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|err| DeviceError::io(context(), err))? {
+            let path = entry.map_err(|err| DeviceError::io(context(), err))?.path();
+            match path.extension().and_then(|it| it.to_str()) {
+                //leftover of an interrupted push
+                Some("tmp") => {
+                    let _ = fs::remove_file(&path);
+                }
+                Some("msg") => {
+                    if let Some(sequence) = path
+                        .file_stem()
+                        .and_then(|it| it.to_str())
+                        .and_then(|it| it.parse::<u64>().ok())
+                    {
+                        sequences.push(sequence);
+                    }
+                }
+                _ => {}
+            }
+        }
+        sequences.sort_unstable();
+
+        let next_sequence = sequences.last().map(|it| it + 1).unwrap_or(0);
+        let mut queue = TelemetryQueue {
+            dir: dir.to_path_buf(),
+            max_messages: max_messages.max(1),
+            entries: sequences.into(),
+            next_sequence,
+            dropped: 0,
+        };
+        queue.enforce_limit();
+
+        if !queue.is_empty() {
+            println!("Telemetry queue: {} messages waiting from the previous run", queue.len());
+        }
+        Ok(queue)
+    }
+
+    pub fn push(&mut self, payload: &[u8]) -> DeviceResult<()> {
+        let sequence = self.next_sequence;
+        let tmp_path = self.dir.join(format!("{:020}.tmp", sequence));
+
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(payload)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, self.message_path(sequence))
+        };
+        write().map_err(|err| DeviceError::io(format!("write {}", tmp_path.display()), err))?;
+        //make the rename itself durable
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        self.next_sequence += 1;
+        self.entries.push_back(sequence);
+        self.enforce_limit();
+        Ok(())
+    }
+
+    /// Oldest message with its sequence number, it stays in the queue until `remove` is called.
+    pub fn peek(&mut self) -> DeviceResult<Option<Entry>> {
+        while let Some(sequence) = self.entries.front().copied() {
+            match fs::read(self.message_path(sequence)) {
+                Ok(payload) => return Ok(Some((sequence, payload))),
+                //removed from outside, skip it
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    self.entries.pop_front();
+                }
+                Err(err) => {
+                    let context = format!("read {}", self.message_path(sequence).display());
+                    return Err(DeviceError::io(context, err));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn remove(&mut self, sequence: u64) -> DeviceResult<()> {
+        self.entries.retain(|it| *it != sequence);
+        let path = self.message_path(sequence);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(DeviceError::io(format!("remove {}", path.display()), err))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Messages dropped because of the retention cap since start.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn enforce_limit(&mut self) {
+        while self.entries.len() > self.max_messages {
+            if let Some(sequence) = self.entries.pop_front() {
+                let _ = fs::remove_file(self.message_path(sequence));
+                self.dropped += 1;
+            }
+        }
+    }
+
+    fn message_path(&self, sequence: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.msg", sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut TelemetryQueue) -> Vec<Vec<u8>> {
+        let mut payloads = Vec::new();
+        while let Some((sequence, payload)) = queue.peek().unwrap() {
+            queue.remove(sequence).unwrap();
+            payloads.push(payload);
+        }
+        payloads
+    }
+
+    #[test]
+    fn keeps_messages_in_order_until_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = TelemetryQueue::open(dir.path(), 10).unwrap();
+        queue.push(b"first").unwrap();
+        queue.push(b"second").unwrap();
+
+        let (sequence, payload) = queue.peek().unwrap().unwrap();
+        assert_eq!(payload, b"first");
+        //peek does not take the message
+        assert_eq!(queue.peek().unwrap().unwrap().0, sequence);
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(drain(&mut queue), vec![b"first".to_vec(), b"second".to_vec()]);
+        assert!(queue.is_empty());
+        assert!(queue.peek().unwrap().is_none());
+    }
+
+    #[test]
+    fn reloads_messages_after_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = TelemetryQueue::open(dir.path(), 10).unwrap();
+        queue.push(b"one").unwrap();
+        queue.push(b"two").unwrap();
+        let (sequence, _) = queue.peek().unwrap().unwrap();
+        queue.remove(sequence).unwrap();
+        drop(queue);
+
+        let mut queue = TelemetryQueue::open(dir.path(), 10).unwrap();
+        assert_eq!(queue.len(), 1);
+        //new messages go after the reloaded ones
+        queue.push(b"three").unwrap();
+        assert_eq!(drain(&mut queue), vec![b"two".to_vec(), b"three".to_vec()]);
+    }
+
+    #[test]
+    fn drops_a_message_cut_off_while_it_was_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = TelemetryQueue::open(dir.path(), 10).unwrap();
+        queue.push(b"complete").unwrap();
+        drop(queue);
+        //power lost before the rename of the next push
+        let partial = dir.path().join(format!("{:020}.tmp", 1));
+        fs::write(&partial, b"compl").unwrap();
+
+        let mut queue = TelemetryQueue::open(dir.path(), 10).unwrap();
+        assert!(!partial.exists());
+        assert_eq!(drain(&mut queue), vec![b"complete".to_vec()]);
+    }
+
+    #[test]
+    fn evicts_the_oldest_messages_above_the_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = TelemetryQueue::open(dir.path(), 3).unwrap();
+        for payload in [b"1", b"2", b"3", b"4", b"5"] {
+            queue.push(payload).unwrap();
+        }
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+
+        //a lower cap after a restart applies to the stored messages too
+        drop(queue);
+        let mut queue = TelemetryQueue::open(dir.path(), 2).unwrap();
+        assert_eq!(drain(&mut queue), vec![b"4".to_vec(), b"5".to_vec()]);
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::ResultTable;
+use crate::error::{DeviceError, DeviceResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordFormat {
+    Csv,
+    Jsonl,
+}
+
+/// Appends every sampled `ResultTable` to a local trace file that can be replayed with `--replay`.
+///
+/// The file is rotated when it grows over `max_bytes` or was open for `max_age` (unless zero),
+/// `trace.jsonl` becomes `trace.jsonl.1` and so on, keeping at most `keep_files` old files. CSV
+/// files hold the columns given to `new`, an existing file with a different header is rotated
+/// away first.
+pub struct Recorder {
+    path: PathBuf,
+    format: RecordFormat,
+    max_bytes: u64,
+    max_age: Duration,
+    keep_files: u32,
+    //csv columns, fixed by the header
+    columns: Vec<String>,
+    file: File,
+    written: u64,
+    //when the current file was opened, an existing file counts from the start of the device
+    opened: Instant,
+}
+
+impl Recorder {
+    pub fn new(
+        path: &Path,
+        max_bytes: u64,
+        max_age: Duration,
+        keep_files: u32,
+        columns: Vec<String>,
+    ) -> DeviceResult<Recorder> {
+        let format = match path.extension().and_then(|it| it.to_str()) {
+            Some("csv") => RecordFormat::Csv,
+            Some("jsonl") => RecordFormat::Jsonl,
+            _ => {
+                return Err(DeviceError::Config(format!(
+                    "Record file has to be .csv or .jsonl: {}",
+                    path.display()
+                )))
+            }
+        };
+        let (file, written) = open_file(path, format, &columns)?;
+
+        let mut recorder = Recorder {
+            path: path.to_path_buf(),
+            format,
+            max_bytes,
+            max_age,
+            keep_files,
+            columns,
+            file,
+            written,
+            opened: Instant::now(),
+        };
+        if format == RecordFormat::Csv && !header_matches(path, &csv_header(&recorder.columns)) {
+            println!("Recorder: sensors changed, rotating {}", path.display());
+            recorder.rotate()?;
+        }
+        Ok(recorder)
+    }
+
+    /// Records the table right after `sensor_id` has been sampled.
+    pub fn record(&mut self, sensor_id: &str, result_table: &ResultTable) -> DeviceResult<()> {
+        let expired = !self.max_age.is_zero() && self.opened.elapsed() >= self.max_age;
+        if self.written >= self.max_bytes || expired {
+            self.rotate()?;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_secs_f64())
+            .unwrap_or_default();
+        let line = match self.format {
+            RecordFormat::Csv => csv_line(timestamp, sensor_id, &self.columns, result_table),
+            RecordFormat::Jsonl => jsonl_line(timestamp, sensor_id, result_table),
+        };
+
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|err| DeviceError::io(format!("write {}", self.path.display()), err))?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> DeviceResult<()> {
+        let rotated = |index: u32| PathBuf::from(format!("{}.{}", self.path.display(), index));
+
+        let result = if self.keep_files == 0 {
+            fs::remove_file(&self.path)
+        } else {
+            let _ = fs::remove_file(rotated(self.keep_files));
+            for index in (1..self.keep_files).rev() {
+                let _ = fs::rename(rotated(index), rotated(index + 1));
+            }
+            fs::rename(&self.path, rotated(1))
+        };
+        result.map_err(|err| DeviceError::io(format!("rotate {}", self.path.display()), err))?;
+
+        let (file, written) = open_file(&self.path, self.format, &self.columns)?;
+        self.file = file;
+        self.written = written;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+//opens for appending, a new csv file starts with the header
+fn open_file(path: &Path, format: RecordFormat, columns: &[String]) -> DeviceResult<(File, u64)> {
+    let open = || -> std::io::Result<(File, u64)> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut written = file.metadata()?.len();
+
+        if format == RecordFormat::Csv && written == 0 {
+            let header = csv_header(columns);
+            file.write_all(header.as_bytes())?;
+            written += header.len() as u64;
+        }
+        Ok((file, written))
+    };
+
+    open().map_err(|err| DeviceError::io(format!("open record file {}", path.display()), err))
+}
+
+fn csv_header(columns: &[String]) -> String {
+    format!("timestamp,sensor,{},demo_switch\n", columns.join(","))
+}
+
+//true for an empty or missing file too, those get the header when opened
+fn header_matches(path: &Path, header: &str) -> bool {
+    let Ok(file) = File::open(path) else {
+        return true;
+    };
+    let mut first_line = String::new();
+    match BufReader::new(file).read_line(&mut first_line) {
+        Ok(0) | Err(_) => true,
+        Ok(_) => first_line.trim_end() == header.trim_end(),
+    }
+}
+
+fn csv_line(timestamp: f64, sensor_id: &str, columns: &[String], result_table: &ResultTable) -> String {
+    let values: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            //unavailable values stay empty, replay treats them as a failed read
+            result_table
+                .get(column)
+                .map(|value| value.to_string())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    format!(
+        "{:.3},{},{},{}\n",
+        timestamp,
+        sensor_id,
+        values.join(","),
+        result_table.demo_switch
+    )
+}
+
+fn jsonl_line(timestamp: f64, sensor_id: &str, result_table: &ResultTable) -> String {
+    let mut object = serde_json::Map::new();
+    object.insert("timestamp".into(), timestamp.into());
+    object.insert("sensor".into(), sensor_id.into());
+    for column in result_table.columns() {
+        object.insert(column.name.clone(), result_table.get(&column.name).into());
+    }
+    object.insert("demo_switch".into(), result_table.demo_switch.into());
+
+    format!("{}\n", serde_json::Value::Object(object))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::sensors::replay::{ReplaySensor, Trace};
+    use crate::engine::sensors::{Quantity, Reading, Sensor};
+
+    const MB: u64 = 1024 * 1024;
+
+    fn columns() -> Vec<String> {
+        vec!["aht20_temp".to_string(), "bmp280_pressure".to_string()]
+    }
+
+    fn table() -> ResultTable {
+        let mut table = ResultTable::default();
+        table.register("aht20", &[Quantity::Temperature]);
+        table.register("bmp280", &[Quantity::Pressure]);
+        table.apply("aht20", &[Reading::new(Quantity::Temperature, 21.5)]);
+        table
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(|it| it.to_string()).collect()
+    }
+
+    fn rotated(path: &Path, index: u32) -> PathBuf {
+        PathBuf::from(format!("{}.{}", path.display(), index))
+    }
+
+    #[test]
+    fn writes_csv_lines_under_the_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.csv");
+        let mut recorder = Recorder::new(&path, MB, Duration::ZERO, 2, columns()).unwrap();
+        recorder.record("aht20", &table()).unwrap();
+
+        let lines = lines(&path);
+        assert_eq!(lines[0], "timestamp,sensor,aht20_temp,bmp280_pressure,demo_switch");
+        let cells: Vec<&str> = lines[1].split(',').collect();
+        assert!(cells[0].parse::<f64>().unwrap() > 1_700_000_000.0);
+        //bmp280 has not answered yet, its cell stays empty
+        assert_eq!(cells[1..], ["aht20", "21.5", "", "false"]);
+    }
+
+    #[test]
+    fn writes_jsonl_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let mut recorder = Recorder::new(&path, MB, Duration::ZERO, 2, columns()).unwrap();
+        recorder.record("aht20", &table()).unwrap();
+
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 1);
+        let object: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert!(object["timestamp"].as_f64().unwrap() > 1_700_000_000.0);
+        assert_eq!(object["sensor"], "aht20");
+        assert_eq!(object["aht20_temp"], 21.5);
+        assert!(object["bmp280_pressure"].is_null());
+        assert_eq!(object["demo_switch"], false);
+    }
+
+    #[test]
+    fn rotates_by_size_and_prunes_old_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        //every line is over the limit, so each record after the first starts a new file
+        let mut recorder = Recorder::new(&path, 10, Duration::ZERO, 2, columns()).unwrap();
+        for sensor in ["a", "b", "c", "d"] {
+            recorder.record(sensor, &table()).unwrap();
+        }
+
+        let sensor = |path: &Path| {
+            let object: serde_json::Value = serde_json::from_str(&lines(path)[0]).unwrap();
+            object["sensor"].as_str().unwrap().to_string()
+        };
+        assert_eq!(sensor(&path), "d");
+        assert_eq!(sensor(&rotated(&path, 1)), "c");
+        assert_eq!(sensor(&rotated(&path, 2)), "b");
+        assert!(!rotated(&path, 3).exists());
+    }
+
+    #[test]
+    fn keep_files_0_keeps_only_the_current_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.csv");
+        let mut recorder = Recorder::new(&path, 10, Duration::ZERO, 0, columns()).unwrap();
+        recorder.record("aht20", &table()).unwrap();
+        recorder.record("bmp280", &table()).unwrap();
+
+        assert!(!rotated(&path, 1).exists());
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("timestamp,"));
+        assert!(lines[1].contains(",bmp280,"));
+    }
+
+    #[test]
+    fn rotates_by_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let mut recorder = Recorder::new(&path, MB, Duration::from_secs(60), 2, columns()).unwrap();
+        recorder.record("aht20", &table()).unwrap();
+        recorder.record("aht20", &table()).unwrap();
+        assert!(!rotated(&path, 1).exists());
+
+        recorder.opened = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+        recorder.record("bmp280", &table()).unwrap();
+        assert_eq!(lines(&rotated(&path, 1)).len(), 2);
+        assert_eq!(lines(&path).len(), 1);
+    }
+
+    #[test]
+    fn a_changed_csv_header_rotates_the_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.csv");
+        fs::write(&path, "timestamp,sensor,dht22_temp,demo_switch\n1.000,dht22,20,false\n").unwrap();
+
+        let mut recorder = Recorder::new(&path, MB, Duration::ZERO, 2, columns()).unwrap();
+        recorder.record("aht20", &table()).unwrap();
+
+        assert_eq!(lines(&rotated(&path, 1))[0], "timestamp,sensor,dht22_temp,demo_switch");
+        let lines = lines(&path);
+        assert_eq!(lines[0], "timestamp,sensor,aht20_temp,bmp280_pressure,demo_switch");
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn an_unchanged_csv_header_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.csv");
+        Recorder::new(&path, MB, Duration::ZERO, 2, columns())
+            .unwrap()
+            .record("aht20", &table())
+            .unwrap();
+        Recorder::new(&path, MB, Duration::ZERO, 2, columns())
+            .unwrap()
+            .record("aht20", &table())
+            .unwrap();
+
+        assert!(!rotated(&path, 1).exists());
+        assert_eq!(lines(&path).len(), 3);
+    }
+
+    #[test]
+    fn rejects_unknown_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.txt");
+        assert!(Recorder::new(&path, MB, Duration::ZERO, 2, columns()).is_err());
+    }
+
+    //what the engine does, the whole table after every sensor, then replayed sensor by sensor
+    fn round_trip(file_name: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(file_name);
+        let mut recorder = Recorder::new(&path, MB, Duration::ZERO, 2, columns()).unwrap();
+
+        let mut table = table();
+        for (aht20, bmp280) in [(21.0, 101.0), (22.0, 102.0)] {
+            table.apply("aht20", &[Reading::new(Quantity::Temperature, aht20)]);
+            recorder.record("aht20", &table).unwrap();
+            table.apply("bmp280", &[Reading::new(Quantity::Pressure, bmp280)]);
+            recorder.record("bmp280", &table).unwrap();
+        }
+
+        let trace = Arc::new(Trace::load(&path).unwrap());
+        let mut aht20 = ReplaySensor::new("aht20", trace.clone(), 1.0);
+        let mut bmp280 = ReplaySensor::new("bmp280", trace, 1.0);
+        let values = |sensor: &mut ReplaySensor| {
+            std::iter::from_fn(|| sensor.read().ok())
+                .map(|readings| readings[0].value)
+                .collect::<Vec<f32>>()
+        };
+        assert_eq!(values(&mut aht20), [21.0, 22.0]);
+        assert_eq!(values(&mut bmp280), [101.0, 102.0]);
+    }
+
+    #[test]
+    fn csv_traces_replay_per_sensor() {
+        round_trip("trace.csv");
+    }
+
+    #[test]
+    fn jsonl_traces_replay_per_sensor() {
+        round_trip("trace.jsonl");
+    }
+}
@@ -0,0 +1,41 @@
+use rppal::hal::Delay;
+
+use super::{Quantity, Reading, Sensor};
+use crate::engine::i2c_bus::SharedI2c;
+use crate::error::{DeviceError, DeviceResult};
+
+pub struct Aht20Sensor {
+    aht20: embedded_aht20::Aht20<SharedI2c, Delay>,
+}
+
+impl Aht20Sensor {
+    pub fn new(i2c: SharedI2c, address: u8) -> DeviceResult<Aht20Sensor> {
+        let aht20 = embedded_aht20::Aht20::new(i2c, address, Delay).map_err(|err| {
+            DeviceError::sensor("aht20", format!("init at {:#x}: {:?}", address, err))
+        })?;
+
+        Ok(Aht20Sensor { aht20 })
+    }
+}
+
+impl Sensor for Aht20Sensor {
+    fn id(&self) -> &str {
+        "aht20"
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        vec![Quantity::Temperature, Quantity::Humidity]
+    }
+
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        let result = self
+            .aht20
+            .measure()
+            .map_err(|err| DeviceError::sensor("aht20", format!("measure: {:?}", err)))?;
+
+        Ok(vec![
+            Reading::new(Quantity::Temperature, result.temperature.celcius()),
+            Reading::new(Quantity::Humidity, result.relative_humidity),
+        ])
+    }
+}
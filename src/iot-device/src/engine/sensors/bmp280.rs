@@ -0,0 +1,204 @@
+use embedded_hal::i2c::I2c;
+
+use super::{Quantity, Reading, Sensor};
+use crate::engine::i2c_bus::SharedI2c;
+use crate::error::{DeviceError, DeviceResult};
+
+const REG_CALIBRATION: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+
+const CHIP_ID: u8 = 0x58;
+//temperature x1, pressure x4 oversampling, normal mode
+#[allow(clippy::unusual_byte_groupings)]
+const CTRL_MEAS_NORMAL: u8 = 0b001_011_11;
+//0.5 ms standby, filter off
+const CONFIG: u8 = 0x00;
+
+//factory trimming values, datasheet section 3.11.2
+#[derive(Debug, Clone, Copy)]
+struct Calibration {
+    t1: f64,
+    t2: f64,
+    t3: f64,
+    p: [f64; 9],
+}
+
+impl Calibration {
+    fn parse(raw: &[u8; 24]) -> Calibration {
+        let unsigned = |index: usize| u16::from_le_bytes([raw[index], raw[index + 1]]) as f64;
+        let signed = |index: usize| i16::from_le_bytes([raw[index], raw[index + 1]]) as f64;
+        Calibration {
+            t1: unsigned(0),
+            t2: signed(2),
+            t3: signed(4),
+            p: [
+                unsigned(6),
+                signed(8),
+                signed(10),
+                signed(12),
+                signed(14),
+                signed(16),
+                signed(18),
+                signed(20),
+                signed(22),
+            ],
+        }
+    }
+
+    //floating point compensation from the datasheet, returns (celsius, fine temperature)
+    fn temperature(&self, adc: f64) -> (f64, f64) {
+        let var1 = (adc / 16384.0 - self.t1 / 1024.0) * self.t2;
+        let var2 = (adc / 131072.0 - self.t1 / 8192.0).powi(2) * self.t3;
+        let t_fine = var1 + var2;
+        (t_fine / 5120.0, t_fine)
+    }
+
+    //pascal, None when the calibration would divide by zero
+    fn pressure(&self, adc: f64, t_fine: f64) -> Option<f64> {
+        let p = &self.p;
+        let mut var1 = t_fine / 2.0 - 64000.0;
+        let mut var2 = var1 * var1 * p[5] / 32768.0;
+        var2 += var1 * p[4] * 2.0;
+        var2 = var2 / 4.0 + p[3] * 65536.0;
+        var1 = (p[2] * var1 * var1 / 524288.0 + p[1] * var1) / 524288.0;
+        var1 = (1.0 + var1 / 32768.0) * p[0];
+        if var1 == 0.0 {
+            return None;
+        }
+        let mut pressure = 1048576.0 - adc;
+        pressure = (pressure - var2 / 4096.0) * 6250.0 / var1;
+        var1 = p[8] * pressure * pressure / 2147483648.0;
+        var2 = pressure * p[7] / 32768.0;
+        Some(pressure + (var1 + var2 + p[6]) / 16.0)
+    }
+}
+
+/// BMP280 on a shared I2C bus, runs in normal mode so every read returns the latest conversion.
+pub struct Bmp280Sensor {
+    i2c: SharedI2c,
+    address: u8,
+    calibration: Calibration,
+}
+
+impl Bmp280Sensor {
+    pub fn new(mut i2c: SharedI2c, address: u8) -> DeviceResult<Bmp280Sensor> {
+        let error = |what: &str, err: rppal::i2c::Error| {
+            DeviceError::sensor("bmp280", format!("{} at {:#x}: {}", what, address, err))
+        };
+
+        let mut chip_id = [0u8; 1];
+        i2c.write_read(address, &[REG_CHIP_ID], &mut chip_id)
+            .map_err(|err| error("read chip id", err))?;
+        if chip_id[0] != CHIP_ID {
+            return Err(DeviceError::sensor(
+                "bmp280",
+                format!("chip id {:#x} at {:#x} is not a BMP280", chip_id[0], address),
+            ));
+        }
+
+        let mut raw = [0u8; 24];
+        i2c.write_read(address, &[REG_CALIBRATION], &mut raw)
+            .map_err(|err| error("read calibration", err))?;
+        i2c.write(address, &[REG_CONFIG, CONFIG])
+            .map_err(|err| error("configure", err))?;
+        i2c.write(address, &[REG_CTRL_MEAS, CTRL_MEAS_NORMAL])
+            .map_err(|err| error("configure", err))?;
+
+        Ok(Bmp280Sensor {
+            i2c,
+            address,
+            calibration: Calibration::parse(&raw),
+        })
+    }
+}
+
+impl Sensor for Bmp280Sensor {
+    fn id(&self) -> &str {
+        "bmp280"
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        vec![Quantity::Temperature, Quantity::Pressure]
+    }
+
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        //pressure then temperature, 20 bit each
+        let mut data = [0u8; 6];
+        self.i2c
+            .write_read(self.address, &[REG_DATA], &mut data)
+            .map_err(|err| DeviceError::sensor("bmp280", format!("read: {}", err)))?;
+        let adc = |index: usize| {
+            ((data[index] as u32) << 12 | (data[index + 1] as u32) << 4 | (data[index + 2] as u32) >> 4) as f64
+        };
+
+        let (temperature, t_fine) = self.calibration.temperature(adc(3));
+        let pressure = self
+            .calibration
+            .pressure(adc(0), t_fine)
+            .ok_or_else(|| DeviceError::sensor("bmp280", "invalid calibration"))?;
+
+        Ok(vec![
+            Reading::new(Quantity::Temperature, temperature as f32),
+            Reading::new(Quantity::Pressure, (pressure / 1000.0) as f32),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the worked example of datasheet section 3.12
+    const ADC_T: f64 = 519888.0;
+    const ADC_P: f64 = 415148.0;
+
+    fn datasheet_calibration() -> Calibration {
+        let values: [i32; 12] = [27504, 26435, -1000, 36477, -10685, 3024, 2855, 140, -7, 15500, -14600, 6000];
+        let mut raw = [0u8; 24];
+        for (index, value) in values.iter().enumerate() {
+            //dig_T1 and dig_P1 are unsigned, the rest signed, both fit the same two bytes
+            let bytes = if index == 0 || index == 3 {
+                (*value as u16).to_le_bytes()
+            } else {
+                (*value as i16).to_le_bytes()
+            };
+            raw[index * 2..index * 2 + 2].copy_from_slice(&bytes);
+        }
+        Calibration::parse(&raw)
+    }
+
+    #[test]
+    fn parses_unsigned_and_signed_words() {
+        let calibration = datasheet_calibration();
+        assert_eq!(calibration.t1, 27504.0);
+        assert_eq!(calibration.t3, -1000.0);
+        assert_eq!(calibration.p[0], 36477.0);
+        assert_eq!(calibration.p[7], -14600.0);
+    }
+
+    #[test]
+    fn compensates_temperature() {
+        let (celsius, t_fine) = datasheet_calibration().temperature(ADC_T);
+        assert!((celsius - 25.08).abs() < 0.01, "{}", celsius);
+        assert!((t_fine - 128422.0).abs() < 1.0, "{}", t_fine);
+    }
+
+    #[test]
+    fn compensates_pressure() {
+        let calibration = datasheet_calibration();
+        let (_, t_fine) = calibration.temperature(ADC_T);
+        let pascal = calibration.pressure(ADC_P, t_fine).unwrap();
+        assert!((pascal - 100653.0).abs() < 1.0, "{}", pascal);
+    }
+
+    #[test]
+    fn zero_calibration_has_no_pressure() {
+        let mut calibration = datasheet_calibration();
+        calibration.p[0] = 0.0;
+        let (_, t_fine) = calibration.temperature(ADC_T);
+        assert_eq!(calibration.pressure(ADC_P, t_fine), None);
+    }
+}
@@ -0,0 +1,56 @@
+use super::{Quantity, Reading, Sensor};
+use crate::error::{DeviceError, DeviceResult};
+
+/// DHT22 read through the kernel IIO driver (dht11 overlay), values are in milli units.
+pub struct Dht22Sensor {
+    fs_temp: String,
+    fs_humidity: String,
+}
+
+impl Dht22Sensor {
+    pub fn new(fs_temp: &str, fs_humidity: &str) -> Dht22Sensor {
+        Dht22Sensor {
+            fs_temp: fs_temp.to_string(),
+            fs_humidity: fs_humidity.to_string(),
+        }
+    }
+}
+
+fn read_milli(path: &str) -> DeviceResult<f32> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| DeviceError::io(format!("dht22: read {}", path), err))?;
+    let parsed = content.trim_end().parse::<f32>().map_err(|err| {
+        DeviceError::sensor("dht22", format!("invalid value '{}' in {}: {}", content.trim_end(), path, err))
+    })?;
+    Ok(parsed / 1000.0)
+}
+
+impl Sensor for Dht22Sensor {
+    fn id(&self) -> &str {
+        "dht22"
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        vec![Quantity::Temperature, Quantity::Humidity]
+    }
+
+    //the driver often fails a single channel, so return whatever could be read
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        let mut readings = Vec::new();
+        let mut last_error = None;
+
+        match read_milli(&self.fs_temp) {
+            Ok(value) => readings.push(Reading::new(Quantity::Temperature, value)),
+            Err(err) => last_error = Some(err),
+        }
+        match read_milli(&self.fs_humidity) {
+            Ok(value) => readings.push(Reading::new(Quantity::Humidity, value)),
+            Err(err) => last_error = Some(err),
+        }
+
+        match last_error {
+            Some(err) if readings.is_empty() => Err(err),
+            _ => Ok(readings),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{Quantity, Reading, Sensor};
+use crate::error::{DeviceError, DeviceResult};
+
+//w1 family code of the DS18B20
+const FAMILY_PREFIX: &str = "28-";
+//scratchpad after power-on, the probe answered before its first conversion
+const POWER_ON_RESET: [u8; 2] = [0x50, 0x05];
+
+/// True for sensor ids of DS18B20 probes, which are their w1 serials, e.g. "28-0316a2795aff".
+pub fn is_probe(sensor_id: &str) -> bool {
+    sensor_id.starts_with(FAMILY_PREFIX)
+}
+
+/// Serials of the DS18B20 probes under `root`, sorted.
+pub fn discover(root: &Path) -> DeviceResult<Vec<String>> {
+    let entries = fs::read_dir(root)
+        .map_err(|err| DeviceError::io(format!("w1: list {}", root.display()), err))?;
+
+    let mut serials: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| is_probe(name))
+        .collect();
+    serials.sort();
+    Ok(serials)
+}
+
+/// Prints the probes under `root` with a reading each.
+pub fn print_probes(root: &Path) {
+    match discover(root) {
+        Ok(serials) if serials.is_empty() => println!("w1: no DS18B20 probes in {}", root.display()),
+        Ok(serials) => {
+            for serial in serials {
+                match Ds18b20Sensor::new(root, &serial).read_celsius() {
+                    Ok(celsius) => println!("{}: {:.3} C", serial, celsius),
+                    Err(err) => println!("{}: {}", serial, err),
+                }
+            }
+        }
+        Err(err) => println!("{}", err),
+    }
+}
+
+/// One DS18B20 probe read through the w1_therm driver.
+///
+/// `w1_slave` is preferred since it carries the scratchpad and its CRC, `temperature` (newer kernels,
+/// CRC checked by the driver) is used when `w1_slave` is missing.
+pub struct Ds18b20Sensor {
+    serial: String,
+    path: PathBuf,
+}
+
+impl Ds18b20Sensor {
+    pub fn new(root: &Path, serial: &str) -> Ds18b20Sensor {
+        Ds18b20Sensor {
+            serial: serial.to_string(),
+            path: root.join(serial),
+        }
+    }
+
+    fn read_celsius(&self) -> DeviceResult<f32> {
+        let w1_slave = self.path.join("w1_slave");
+        let (path, parsed) = if w1_slave.exists() {
+            let content = self.read_file(&w1_slave)?;
+            (w1_slave, parse_w1_slave(&content))
+        } else {
+            let temperature = self.path.join("temperature");
+            let content = self.read_file(&temperature)?;
+            (temperature, parse_milli(&content))
+        };
+        parsed.map_err(|err| DeviceError::sensor(&self.serial, format!("{}: {}", path.display(), err)))
+    }
+
+    fn read_file(&self, path: &Path) -> DeviceResult<String> {
+        fs::read_to_string(path)
+            .map_err(|err| DeviceError::io(format!("{}: read {}", self.serial, path.display()), err))
+    }
+}
+
+//  72 01 4b 46 7f ff 0e 10 57 : crc=57 YES
+//  72 01 4b 46 7f ff 0e 10 57 t=23125
+fn parse_w1_slave(content: &str) -> Result<f32, String> {
+    let mut lines = content.lines();
+    let status = lines.next().ok_or("empty")?;
+    let data = lines.next().ok_or("missing temperature line")?;
+
+    let (bytes, verdict) = status.split_once(':').ok_or("missing crc")?;
+    let scratchpad = bytes
+        .split_whitespace()
+        .map(|it| u8::from_str_radix(it, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|err| format!("invalid scratchpad '{}': {}", bytes.trim(), err))?;
+    if scratchpad.len() != 9 {
+        return Err(format!("scratchpad has {} bytes, expected 9", scratchpad.len()));
+    }
+    //a disconnected data line reads as zeros, which pass the crc
+    if scratchpad.iter().all(|it| *it == 0) {
+        return Err("no response, scratchpad is empty".into());
+    }
+    let crc = crc8(&scratchpad[..8]);
+    if crc != scratchpad[8] {
+        return Err(format!("crc mismatch, computed {:02x} read {:02x}", crc, scratchpad[8]));
+    }
+    if !verdict.trim_end().ends_with("YES") {
+        return Err("driver reported crc NO".into());
+    }
+    if scratchpad[..2] == POWER_ON_RESET {
+        return Err("power-on value, no conversion yet".into());
+    }
+
+    let (_, milli) = data.rsplit_once("t=").ok_or("missing t=")?;
+    parse_milli(milli)
+}
+
+fn parse_milli(content: &str) -> Result<f32, String> {
+    let milli = content
+        .trim()
+        .parse::<i32>()
+        .map_err(|err| format!("invalid value '{}': {}", content.trim(), err))?;
+    Ok(milli as f32 / 1000.0)
+}
+
+//Dallas/Maxim CRC-8, polynomial x^8 + x^5 + x^4 + 1, least significant bit first
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in bytes {
+        let mut byte = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            byte >>= 1;
+        }
+    }
+    crc
+}
+
+impl Sensor for Ds18b20Sensor {
+    fn id(&self) -> &str {
+        &self.serial
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        vec![Quantity::Temperature]
+    }
+
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        Ok(vec![Reading::new(Quantity::Temperature, self.read_celsius()?)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = concat!(
+        "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n",
+        "72 01 4b 46 7f ff 0e 10 57 t=23125\n",
+    );
+
+    #[test]
+    fn crc8_of_a_rom_id() {
+        //ROM example of Maxim application note 27, family 02, serial 00000001b81c, crc a2
+        let rom = [0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2];
+        assert_eq!(crc8(&rom[..7]), 0xA2);
+        //the crc over the data and its own crc is zero
+        assert_eq!(crc8(&rom), 0);
+    }
+
+    #[test]
+    fn parses_a_valid_scratchpad() {
+        assert_eq!(parse_w1_slave(VALID), Ok(23.125));
+    }
+
+    #[test]
+    fn parses_negative_temperatures() {
+        let content = concat!(
+            "5e ff 4b 46 7f ff 0c 10 6a : crc=6a YES\n",
+            "5e ff 4b 46 7f ff 0c 10 6a t=-10125\n",
+        );
+        assert_eq!(parse_w1_slave(content), Ok(-10.125));
+    }
+
+    #[test]
+    fn rejects_a_crc_the_driver_refused() {
+        let content = VALID.replace("YES", "NO");
+        assert_eq!(parse_w1_slave(&content), Err("driver reported crc NO".to_string()));
+    }
+
+    #[test]
+    fn rejects_a_corrupted_scratchpad() {
+        //one flipped bit in the temperature, the driver line still says YES
+        let content = VALID.replace("72 01 4b", "73 01 4b");
+        assert!(parse_w1_slave(&content).unwrap_err().contains("crc mismatch"));
+    }
+
+    #[test]
+    fn rejects_malformed_content() {
+        assert_eq!(parse_w1_slave(""), Err("empty".to_string()));
+        assert_eq!(
+            parse_w1_slave("72 01 4b 46 7f ff 0e 10 57 : crc=57 YES\n"),
+            Err("missing temperature line".to_string())
+        );
+        assert!(parse_w1_slave("72 01 zz 46 7f ff 0e 10 57 : crc=57 YES\nt=1\n")
+            .unwrap_err()
+            .starts_with("invalid scratchpad"));
+        assert!(parse_w1_slave("72 01 4b : crc=57 YES\nt=1\n")
+            .unwrap_err()
+            .contains("expected 9"));
+        let no_value = VALID.replace("t=23125", "");
+        assert_eq!(parse_w1_slave(&no_value), Err("missing t=".to_string()));
+        let bad_value = VALID.replace("t=23125", "t=2x");
+        assert!(parse_w1_slave(&bad_value).unwrap_err().starts_with("invalid value"));
+    }
+
+    #[test]
+    fn rejects_an_empty_scratchpad() {
+        let content = concat!(
+            "00 00 00 00 00 00 00 00 00 : crc=00 YES\n",
+            "00 00 00 00 00 00 00 00 00 t=0\n",
+        );
+        assert!(parse_w1_slave(content).unwrap_err().contains("no response"));
+    }
+
+    #[test]
+    fn rejects_the_power_on_value() {
+        let content = concat!(
+            "50 05 4b 46 7f ff 0c 10 1c : crc=1c YES\n",
+            "50 05 4b 46 7f ff 0c 10 1c t=85000\n",
+        );
+        assert!(parse_w1_slave(content).unwrap_err().contains("power-on"));
+    }
+
+    #[test]
+    fn discovers_and_reads_probes() {
+        let root = tempfile::tempdir().unwrap();
+        for (name, file, content) in [
+            ("28-0316a2795aff", "w1_slave", VALID),
+            ("28-0000075b1c2d", "temperature", "-1500\n"),
+            ("w1_bus_master1", "w1_master_slaves", "28-0316a2795aff\n"),
+        ] {
+            fs::create_dir(root.path().join(name)).unwrap();
+            fs::write(root.path().join(name).join(file), content).unwrap();
+        }
+
+        let serials = discover(root.path()).unwrap();
+        assert_eq!(serials, ["28-0000075b1c2d", "28-0316a2795aff"]);
+
+        let mut probe = Ds18b20Sensor::new(root.path(), "28-0316a2795aff");
+        assert_eq!(probe.read().unwrap(), [Reading::new(Quantity::Temperature, 23.125)]);
+        //newer kernels without w1_slave
+        let mut probe = Ds18b20Sensor::new(root.path(), "28-0000075b1c2d");
+        assert_eq!(probe.read().unwrap(), [Reading::new(Quantity::Temperature, -1.5)]);
+
+        let refused = VALID.replace("YES", "NO");
+        fs::write(root.path().join("28-0316a2795aff/w1_slave"), refused).unwrap();
+        let mut probe = Ds18b20Sensor::new(root.path(), "28-0316a2795aff");
+        assert!(probe.read().is_err());
+    }
+}
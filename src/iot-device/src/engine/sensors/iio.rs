@@ -0,0 +1,399 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{Quantity, Reading, Sensor};
+use crate::engine::config::IioSensorConfig;
+use crate::error::{DeviceError, DeviceResult};
+
+//channel types of the IIO ABI and what they measure, used when a sensor binds no channels
+const KNOWN_CHANNELS: [(&str, Quantity); 3] = [
+    ("temp", Quantity::Temperature),
+    ("humidityrelative", Quantity::Humidity),
+    ("pressure", Quantity::Pressure),
+];
+
+/// An entry of the IIO sysfs root, e.g. `/sys/bus/iio/devices/iio:device0`.
+#[derive(Debug, Clone)]
+pub struct IioDevice {
+    pub path: PathBuf,
+    //directory name, "iio:device0"
+    pub dir_name: String,
+    //contents of the `name` file, "dht11", "bme280"
+    pub name: String,
+    //readable channels, "temp" for in_temp_input or in_temp_raw, "temp0" for in_temp0_input
+    pub channels: Vec<String>,
+}
+
+impl IioDevice {
+    fn matches(&self, device: &str) -> bool {
+        self.dir_name == device || self.name == device
+    }
+}
+
+/// Lists the IIO devices under `root`, sorted by directory name.
+pub fn enumerate(root: &Path) -> DeviceResult<Vec<IioDevice>> {
+    let entries = fs::read_dir(root)
+        .map_err(|err| DeviceError::io(format!("iio: list {}", root.display()), err))?;
+
+    let mut devices = Vec::new();
+    for entry in entries.flatten() {
+        let dir_name = entry.file_name().to_string_lossy().to_string();
+        //triggers and buffers live next to the devices
+        if !dir_name.starts_with("iio:device") {
+            continue;
+        }
+        let path = entry.path();
+        let name = fs::read_to_string(path.join("name"))
+            .map(|it| it.trim().to_string())
+            .unwrap_or_default();
+        devices.push(IioDevice {
+            channels: channels(&path),
+            path,
+            dir_name,
+            name,
+        });
+    }
+    devices.sort_by(|a, b| a.dir_name.cmp(&b.dir_name));
+    Ok(devices)
+}
+
+fn channels(path: &Path) -> Vec<String> {
+    let mut channels: Vec<String> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let channel = file_name.strip_prefix("in_")?;
+            let channel = channel
+                .strip_suffix("_input")
+                .or_else(|| channel.strip_suffix("_raw"))?;
+            Some(channel.to_string())
+        })
+        .collect();
+    channels.sort();
+    channels.dedup();
+    channels
+}
+
+/// Prints the IIO devices under `root` with their channels.
+pub fn print_devices(root: &Path) {
+    match enumerate(root) {
+        Ok(devices) if devices.is_empty() => println!("iio: no devices in {}", root.display()),
+        Ok(devices) => {
+            for device in devices {
+                println!(
+                    "{} ({}): {}",
+                    device.dir_name,
+                    device.name,
+                    device.channels.join(", ")
+                );
+            }
+        }
+        Err(err) => println!("{}", err),
+    }
+}
+
+//where the value of a channel comes from
+#[derive(Debug)]
+enum Source {
+    //already scaled by the driver
+    Input(PathBuf),
+    //(raw + offset) * scale, the attributes are read on every read since drivers may change them
+    Raw {
+        raw: PathBuf,
+        scale: Option<PathBuf>,
+        offset: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug)]
+struct Binding {
+    quantity: Quantity,
+    channel: String,
+    source: Source,
+}
+
+/// Sensor reading bound channels of one IIO device.
+pub struct IioSensor {
+    id: String,
+    bindings: Vec<Binding>,
+}
+
+impl IioSensor {
+    /// Finds the device of `config` under `root` and resolves its channel bindings.
+    pub fn open(root: &Path, config: &IioSensorConfig) -> DeviceResult<IioSensor> {
+        let device = enumerate(root)?
+            .into_iter()
+            .find(|it| it.matches(&config.device))
+            .ok_or_else(|| {
+                DeviceError::sensor(
+                    &config.id,
+                    format!("no IIO device '{}' in {}", config.device, root.display()),
+                )
+            })?;
+
+        let channels = &config.channels;
+        let configured: Vec<(Quantity, &String)> = [
+            (Quantity::Temperature, channels.temperature.as_ref()),
+            (Quantity::Humidity, channels.humidity.as_ref()),
+            (Quantity::Pressure, channels.pressure.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(quantity, channel)| Some((quantity, channel?)))
+        .collect();
+
+        let mut bindings = Vec::new();
+        if configured.is_empty() {
+            for (channel, quantity) in KNOWN_CHANNELS {
+                if let Some(source) = source(&device.path, channel) {
+                    bindings.push(Binding {
+                        quantity,
+                        channel: channel.to_string(),
+                        source,
+                    });
+                }
+            }
+        } else {
+            for (quantity, channel) in configured {
+                let source = source(&device.path, channel).ok_or_else(|| {
+                    DeviceError::sensor(
+                        &config.id,
+                        format!(
+                            "{} ({}) has no channel '{}', available: {}",
+                            device.dir_name,
+                            device.name,
+                            channel,
+                            device.channels.join(", ")
+                        ),
+                    )
+                })?;
+                bindings.push(Binding {
+                    quantity,
+                    channel: channel.clone(),
+                    source,
+                });
+            }
+        }
+
+        if bindings.is_empty() {
+            return Err(DeviceError::sensor(
+                &config.id,
+                format!("{} ({}) has no known channels", device.dir_name, device.name),
+            ));
+        }
+        println!(
+            "{}: {} ({}) channels {}",
+            config.id,
+            device.dir_name,
+            device.name,
+            bindings
+                .iter()
+                .map(|it| it.channel.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        Ok(IioSensor {
+            id: config.id.clone(),
+            bindings,
+        })
+    }
+
+    fn read_binding(&self, binding: &Binding) -> DeviceResult<f32> {
+        let value = match &binding.source {
+            Source::Input(path) => self.read_number(path)?,
+            Source::Raw { raw, scale, offset } => {
+                let raw = self.read_number(raw)?;
+                let offset = match offset {
+                    Some(path) => self.read_number(path)?,
+                    None => 0.0,
+                };
+                let scale = match scale {
+                    Some(path) => self.read_number(path)?,
+                    None => 1.0,
+                };
+                (raw + offset) * scale
+            }
+        };
+        Ok(to_unit(binding.quantity, value) as f32)
+    }
+
+    fn read_number(&self, path: &Path) -> DeviceResult<f64> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| DeviceError::io(format!("{}: read {}", self.id, path.display()), err))?;
+        content.trim().parse::<f64>().map_err(|err| {
+            DeviceError::sensor(
+                &self.id,
+                format!("invalid value '{}' in {}: {}", content.trim(), path.display(), err),
+            )
+        })
+    }
+}
+
+//in_<channel>_input, otherwise in_<channel>_raw with its scale and offset
+fn source(device: &Path, channel: &str) -> Option<Source> {
+    let input = device.join(format!("in_{}_input", channel));
+    if input.exists() {
+        return Some(Source::Input(input));
+    }
+    let raw = device.join(format!("in_{}_raw", channel));
+    if !raw.exists() {
+        return None;
+    }
+    Some(Source::Raw {
+        raw,
+        scale: attribute(device, channel, "scale"),
+        offset: attribute(device, channel, "offset"),
+    })
+}
+
+//per channel attribute, or the one shared by the channel type: in_temp0_scale then in_temp_scale
+fn attribute(device: &Path, channel: &str, attribute: &str) -> Option<PathBuf> {
+    let own = device.join(format!("in_{}_{}", channel, attribute));
+    if own.exists() {
+        return Some(own);
+    }
+    let channel_type = channel.trim_end_matches(|it: char| it.is_ascii_digit());
+    let shared = device.join(format!("in_{}_{}", channel_type, attribute));
+    shared.exists().then_some(shared)
+}
+
+//IIO reports temperature and relative humidity in milli units, pressure already in kPa
+fn to_unit(quantity: Quantity, value: f64) -> f64 {
+    match quantity {
+        Quantity::Temperature | Quantity::Humidity => value / 1000.0,
+        Quantity::Pressure => value,
+    }
+}
+
+impl Sensor for IioSensor {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        self.bindings.iter().map(|it| it.quantity).collect()
+    }
+
+    //like dht22 a single failing channel still returns the others
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        let mut readings = Vec::new();
+        let mut last_error = None;
+        for binding in self.bindings.iter() {
+            match self.read_binding(binding) {
+                Ok(value) => readings.push(Reading::new(binding.quantity, value)),
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        match last_error {
+            Some(err) if readings.is_empty() => Err(err),
+            _ => Ok(readings),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::config::IioChannelsConfig;
+
+    //iio:device0 is a dht11 with scaled inputs, iio:device1 a raw temperature channel with
+    //scale and offset
+    fn iio_root() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let files = [
+            ("iio:device0/name", "dht11\n"),
+            ("iio:device0/in_temp_input", "21500\n"),
+            ("iio:device0/in_humidityrelative_input", "45250\n"),
+            ("iio:device1/name", "tmp117\n"),
+            ("iio:device1/in_temp_raw", "2000\n"),
+            ("iio:device1/in_temp_scale", "7.8125\n"),
+            ("iio:device1/in_temp_offset", "-100\n"),
+            ("trigger0/name", "sysfstrig0\n"),
+        ];
+        for (path, content) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn config(device: &str, channels: IioChannelsConfig) -> IioSensorConfig {
+        IioSensorConfig {
+            id: "iio".to_string(),
+            device: device.to_string(),
+            channels,
+            ..IioSensorConfig::default()
+        }
+    }
+
+    fn open(root: &tempfile::TempDir, device: &str) -> DeviceResult<IioSensor> {
+        IioSensor::open(root.path(), &config(device, IioChannelsConfig::default()))
+    }
+
+    #[test]
+    fn enumerates_devices_and_channels() {
+        let root = iio_root();
+        let devices = enumerate(root.path()).unwrap();
+
+        let names: Vec<(&str, &str)> = devices
+            .iter()
+            .map(|it| (it.dir_name.as_str(), it.name.as_str()))
+            .collect();
+        assert_eq!(names, [("iio:device0", "dht11"), ("iio:device1", "tmp117")]);
+        assert_eq!(devices[0].channels, ["humidityrelative", "temp"]);
+        assert_eq!(devices[1].channels, ["temp"]);
+    }
+
+    #[test]
+    fn reads_scaled_inputs_of_known_channels() {
+        let root = iio_root();
+        let mut sensor = open(&root, "dht11").unwrap();
+
+        assert_eq!(sensor.quantities(), [Quantity::Temperature, Quantity::Humidity]);
+        let readings = sensor.read().unwrap();
+        assert_eq!(readings[0], Reading::new(Quantity::Temperature, 21.5));
+        assert_eq!(readings[1], Reading::new(Quantity::Humidity, 45.25));
+    }
+
+    #[test]
+    fn applies_offset_and_scale_to_raw_channels() {
+        let root = iio_root();
+        let mut sensor = open(&root, "iio:device1").unwrap();
+
+        //(2000 - 100) * 7.8125 m°C
+        let readings = sensor.read().unwrap();
+        assert_eq!(readings, [Reading::new(Quantity::Temperature, 14.84375)]);
+    }
+
+    #[test]
+    fn rejects_missing_devices_and_channels() {
+        let root = iio_root();
+        assert!(open(&root, "bme280").is_err());
+
+        let channels = IioChannelsConfig {
+            pressure: Some("pressure".to_string()),
+            ..IioChannelsConfig::default()
+        };
+        let err = IioSensor::open(root.path(), &config("dht11", channels)).err().unwrap();
+        assert!(err.to_string().contains("available: humidityrelative, temp"), "{}", err);
+    }
+
+    #[test]
+    fn a_failing_channel_returns_the_others() {
+        let root = iio_root();
+        let mut sensor = open(&root, "dht11").unwrap();
+
+        //the dht11 driver reports EIO on a bad checksum, an unparsable value fails the same way
+        fs::write(root.path().join("iio:device0/in_humidityrelative_input"), "garbage\n").unwrap();
+        assert_eq!(sensor.read().unwrap(), [Reading::new(Quantity::Temperature, 21.5)]);
+
+        fs::remove_file(root.path().join("iio:device0/in_temp_input")).unwrap();
+        assert!(sensor.read().is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use super::config::SensorsConfig;
+use super::i2c_bus::I2cBusManager;
+use super::i2c_scan::{self, Chip};
+use crate::error::DeviceResult;
+
+pub mod aht20;
+pub mod bmp280;
+pub mod dht22;
+pub mod ds18b20;
+pub mod iio;
+pub mod replay;
+pub mod simulated;
+
+pub use self::aht20::Aht20Sensor;
+pub use self::bmp280::Bmp280Sensor;
+pub use self::dht22::Dht22Sensor;
+pub use self::ds18b20::Ds18b20Sensor;
+pub use self::iio::IioSensor;
+pub use self::replay::{replay_sensors, ReplaySensor, Trace};
+pub use self::simulated::{simulated_sensors, SimulatedSensor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quantity {
+    Temperature, //celsius
+    Humidity,    //hum %
+    Pressure,    //kpa
+}
+
+impl Quantity {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Quantity::Temperature => "C",
+            Quantity::Humidity => "%",
+            Quantity::Pressure => "kPa",
+        }
+    }
+
+    /// Suffix of the `ResultTable` column names.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Quantity::Temperature => "temp",
+            Quantity::Humidity => "humidity",
+            Quantity::Pressure => "pressure",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Quantity> {
+        match key {
+            "temp" => Some(Quantity::Temperature),
+            "humidity" => Some(Quantity::Humidity),
+            "pressure" => Some(Quantity::Pressure),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub quantity: Quantity,
+    pub value: f32,
+}
+
+impl Reading {
+    pub fn new(quantity: Quantity, value: f32) -> Reading {
+        Reading { quantity, value }
+    }
+}
+
+/// A source of measurements sampled by the `Engine`.
+///
+/// Implementations may block (I2C transactions, sysfs reads), the engine calls
+/// `read` only when the sensor's timer fires.
+pub trait Sensor: Send {
+    /// Short identifier of the sensor, e.g. "aht20". Used as a key in `ResultTable` and logs.
+    fn id(&self) -> &str;
+
+    /// What `read` returns, each one gets a column in `ResultTable`.
+    fn quantities(&self) -> Vec<Quantity>;
+
+    fn read(&mut self) -> DeviceResult<Vec<Reading>>;
+
+    /// Offsets from the start of sampling at which `read` is due, for a sensor that sets its own
+    /// pace like a replayed trace. None reads on the configured interval until the device stops.
+    fn schedule(&self) -> Option<Vec<Duration>> {
+        None
+    }
+}
+
+/// Sensors wired on the Raspberry Pi board. A sensor that cannot be opened is skipped.
+///
+/// I2C sensors get their bus from `i2c`, sensors on the same adapter share one handle.
+pub fn hardware_sensors(config: &SensorsConfig, i2c: &mut I2cBusManager) -> Vec<Box<dyn Sensor>> {
+    let mut sensors: Vec<Box<dyn Sensor>> = Vec::new();
+
+    if config.dht22.enabled {
+        sensors.push(Box::new(Dht22Sensor::new(
+            &config.dht22.temp_path,
+            &config.dht22.humidity_path,
+        )));
+    }
+
+    if config.aht20.enabled {
+        let sensor = i2c
+            .device(config.aht20.bus, config.aht20.address, "aht20")
+            .and_then(|bus| Aht20Sensor::new(bus, config.aht20.address));
+        match sensor {
+            Ok(sensor) => sensors.push(Box::new(sensor)),
+            Err(err) => println!("Could not open aht20: {}", err),
+        }
+    }
+
+    if config.bmp280.enabled {
+        //validated to 0x76 or 0x77
+        let address = config.bmp280.address as u8;
+        let sensor = i2c
+            .device(config.bmp280.bus, address, "bmp280")
+            .and_then(|bus| Bmp280Sensor::new(bus, address));
+        match sensor {
+            Ok(sensor) => sensors.push(Box::new(sensor)),
+            Err(err) => println!("Could not open bmp280: {}", err),
+        }
+    }
+
+    ds18b20_sensors(config, &mut sensors);
+    iio_sensors(config, &mut sensors);
+
+    sensors
+}
+
+/// Like `hardware_sensors`, but the I2C sensors come from probing `config.i2c_buses`.
+///
+/// The first AHT20 and BMP280 found are used whatever their bus and address when their section is enabled,
+/// other chips are only logged.
+pub fn autodetected_sensors(config: &SensorsConfig, i2c: &mut I2cBusManager) -> Vec<Box<dyn Sensor>> {
+    let mut sensors: Vec<Box<dyn Sensor>> = Vec::new();
+
+    if config.dht22.enabled {
+        sensors.push(Box::new(Dht22Sensor::new(
+            &config.dht22.temp_path,
+            &config.dht22.humidity_path,
+        )));
+    }
+
+    let mut found = Vec::new();
+    for bus in config.i2c_buses.iter() {
+        match i2c.bus(*bus) {
+            Ok(mut handle) => found.extend(i2c_scan::scan_bus(&mut handle, *bus)),
+            Err(err) => println!("Autodetect: {}", err),
+        }
+    }
+
+    for detected in found {
+        println!("Autodetect: {}", detected);
+        let taken = |id: &str| sensors.iter().any(|it| it.id() == id);
+        let sensor: DeviceResult<Box<dyn Sensor>> = match detected.chip {
+            Chip::Aht20 if config.aht20.enabled && !taken("aht20") => i2c
+                .device(detected.bus, detected.address, "aht20")
+                .and_then(|bus| Aht20Sensor::new(bus, detected.address))
+                .map(|it| Box::new(it) as Box<dyn Sensor>),
+            Chip::Bmp280 if config.bmp280.enabled && !taken("bmp280") => i2c
+                .device(detected.bus, detected.address, "bmp280")
+                .and_then(|bus| Bmp280Sensor::new(bus, detected.address))
+                .map(|it| Box::new(it) as Box<dyn Sensor>),
+            _ => continue,
+        };
+        match sensor {
+            Ok(sensor) => sensors.push(sensor),
+            Err(err) => println!("Autodetect: could not open {}: {}", detected, err),
+        }
+    }
+
+    ds18b20_sensors(config, &mut sensors);
+    iio_sensors(config, &mut sensors);
+
+    sensors
+}
+
+//one sensor per probe, probes plugged in later need a restart
+fn ds18b20_sensors(config: &SensorsConfig, sensors: &mut Vec<Box<dyn Sensor>>) {
+    if !config.ds18b20.enabled {
+        return;
+    }
+    match ds18b20::discover(&config.ds18b20.w1_root) {
+        Ok(serials) => {
+            for serial in serials {
+                println!("Found DS18B20 {}", serial);
+                sensors.push(Box::new(Ds18b20Sensor::new(&config.ds18b20.w1_root, &serial)));
+            }
+        }
+        //no w1 overlay loaded
+        Err(err) => println!("No DS18B20 probes: {}", err),
+    }
+}
+
+fn iio_sensors(config: &SensorsConfig, sensors: &mut Vec<Box<dyn Sensor>>) {
+    for iio in config.iio.iter() {
+        match IioSensor::open(&config.iio_root, iio) {
+            Ok(sensor) => sensors.push(Box::new(sensor)),
+            Err(err) => println!("Could not open {}: {}", iio.id, err),
+        }
+    }
+}
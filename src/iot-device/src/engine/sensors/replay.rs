@@ -0,0 +1,421 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use super::{Quantity, Reading, Sensor};
+use crate::engine::parse_column_name;
+use crate::error::{DeviceError, DeviceResult};
+
+struct TraceRow {
+    offset: Duration,
+    //the sensor sampled on this row, the `sensor` column of recorded traces
+    sensor: Option<String>,
+    //column name of ResultTable -> value, missing or empty cells are not stored
+    values: HashMap<String, f32>,
+}
+
+/// Timestamped `ResultTable` samples loaded from a .csv or .jsonl file.
+///
+/// CSV files need a header with a `timestamp` column and any of the `ResultTable` columns,
+/// JSONL files hold one object per line with the same keys. Timestamps are unix seconds
+/// or RFC 3339 strings and must not go back. An optional `sensor` column names the sensor
+/// sampled on the row, like the recorder writes it.
+pub struct Trace {
+    rows: Vec<TraceRow>,
+}
+
+impl Trace {
+    pub fn load(path: &Path) -> DeviceResult<Trace> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| DeviceError::io(format!("read trace {}", path.display()), err))?;
+
+        let parsed = match path.extension().and_then(|it| it.to_str()) {
+            Some("csv") => parse_csv(&content),
+            Some("jsonl") | Some("json") => parse_jsonl(&content),
+            _ => Err("unknown format, expected .csv or .jsonl".to_string()),
+        };
+        let samples =
+            parsed.map_err(|err| DeviceError::Trace(format!("{}: {}", path.display(), err)))?;
+        if samples.is_empty() {
+            return Err(DeviceError::Trace(format!("{} has no samples", path.display())));
+        }
+
+        for (index, pair) in samples.windows(2).enumerate() {
+            if pair[1].0 < pair[0].0 {
+                return Err(DeviceError::Trace(format!(
+                    "{}: sample {} at {} is before the previous one at {}",
+                    path.display(),
+                    index + 2,
+                    pair[1].0,
+                    pair[0].0
+                )));
+            }
+        }
+        let first = samples[0].0;
+        let rows = samples
+            .into_iter()
+            .map(|(timestamp, sensor, values)| TraceRow {
+                offset: Duration::from_secs_f64(timestamp - first),
+                sensor,
+                values,
+            })
+            .collect();
+
+        Ok(Trace { rows })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.rows.last().map(|it| it.offset).unwrap_or_default()
+    }
+
+    /// Measurement columns found in the trace, `(sensor id, quantity, column name)` sorted by name.
+    pub fn columns(&self) -> Vec<(String, Quantity, String)> {
+        let mut names: Vec<&String> = self.rows.iter().flat_map(|it| it.values.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (sensor_id, quantity) = parse_column_name(name)?;
+                Some((sensor_id.to_string(), quantity, name.clone()))
+            })
+            .collect()
+    }
+
+}
+
+//unix seconds, the sampled sensor and the values of one line
+type Sample = (f64, Option<String>, HashMap<String, f32>);
+
+fn parse_timestamp(text: &str) -> Result<f64, String> {
+    if let Ok(seconds) = text.parse::<f64>() {
+        //"nan" and "inf" parse as well
+        if !seconds.is_finite() {
+            return Err(format!("invalid timestamp '{}'", text));
+        }
+        return Ok(seconds);
+    }
+    let time = humantime::parse_rfc3339_weak(text)
+        .map_err(|err| format!("invalid timestamp '{}': {}", text, err))?;
+    time.duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs_f64())
+        .map_err(|_| format!("timestamp '{}' is before 1970", text))
+}
+
+fn parse_csv(content: &str) -> Result<Vec<Sample>, String> {
+    let mut lines = content.lines().filter(|it| !it.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or("empty csv trace")?
+        .split(',')
+        .map(|it| it.trim())
+        .collect();
+    let timestamp_column = header
+        .iter()
+        .position(|it| *it == "timestamp")
+        .ok_or("csv trace has no timestamp column")?;
+
+    let mut samples = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let cells: Vec<&str> = line.split(',').map(|it| it.trim()).collect();
+        let timestamp = cells
+            .get(timestamp_column)
+            .ok_or_else(|| format!("Line {}: missing timestamp", number + 2))
+            .and_then(|it| {
+                parse_timestamp(it).map_err(|err| format!("Line {}: {}", number + 2, err))
+            })?;
+
+        let mut sensor = None;
+        let mut values = HashMap::new();
+        for (column, cell) in header.iter().zip(cells.iter()) {
+            if *column == "timestamp" || cell.is_empty() {
+                continue;
+            }
+            if *column == "sensor" {
+                sensor = Some(cell.to_string());
+            } else if let Ok(value) = cell.parse::<f32>() {
+                values.insert(column.to_string(), value);
+            }
+        }
+        samples.push((timestamp, sensor, values));
+    }
+
+    Ok(samples)
+}
+
+fn parse_jsonl(content: &str) -> Result<Vec<Sample>, String> {
+    let mut samples = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)
+            .map_err(|err| format!("Line {}: {}", number + 1, err))?;
+
+        let timestamp = match object.get("timestamp") {
+            Some(serde_json::Value::Number(it)) => it.as_f64().unwrap_or_default(),
+            Some(serde_json::Value::String(it)) => {
+                parse_timestamp(it).map_err(|err| format!("Line {}: {}", number + 1, err))?
+            }
+            _ => return Err(format!("Line {}: missing timestamp", number + 1)),
+        };
+
+        let sensor = object
+            .get("sensor")
+            .and_then(|it| it.as_str())
+            .map(|it| it.to_string());
+        let values = object
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_f64()? as f32)))
+            .filter(|(key, _)| key != "timestamp")
+            .collect();
+        samples.push((timestamp, sensor, values));
+    }
+
+    Ok(samples)
+}
+
+/// Plays back the columns of one sensor from a shared `Trace`.
+///
+/// Every read returns the next row of the sensor, and `schedule` times the reads by the row
+/// timestamps, so a replay gives the same readings in the same order at any speed. Rows naming
+/// another sensor in the `sensor` column are left to that sensor.
+pub struct ReplaySensor {
+    id: String,
+    columns: Vec<(Quantity, String)>,
+    trace: Arc<Trace>,
+    //indices of the trace rows this sensor reads
+    rows: Vec<usize>,
+    //index into rows of the row the next read returns
+    next_row: usize,
+    speed: f32,
+}
+
+impl ReplaySensor {
+    pub fn new(id: &str, trace: Arc<Trace>, speed: f32) -> ReplaySensor {
+        let columns = trace
+            .columns()
+            .into_iter()
+            .filter(|(sensor, _, _)| sensor == id)
+            .map(|(_, quantity, column)| (quantity, column))
+            .collect();
+        let rows = trace
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.sensor.as_ref().is_none_or(|sensor| sensor == id))
+            .map(|(index, _)| index)
+            .collect();
+
+        ReplaySensor {
+            id: id.to_string(),
+            columns,
+            trace,
+            rows,
+            next_row: 0,
+            speed,
+        }
+    }
+}
+
+impl Sensor for ReplaySensor {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        self.columns.iter().map(|(quantity, _)| *quantity).collect()
+    }
+
+    //a row without values for this sensor replays a failed read
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        let Some(row) = self.rows.get(self.next_row).map(|index| &self.trace.rows[*index]) else {
+            return Err(DeviceError::Trace(format!("{}: end of trace", self.id)));
+        };
+        self.next_row += 1;
+
+        let readings: Vec<Reading> = self
+            .columns
+            .iter()
+            .filter_map(|(quantity, column)| {
+                let value = row.values.get(column)?;
+                Some(Reading::new(*quantity, *value))
+            })
+            .collect();
+
+        if readings.is_empty() {
+            return Err(DeviceError::sensor(
+                &self.id,
+                format!("no value in trace at {:?}", row.offset),
+            ));
+        }
+        Ok(readings)
+    }
+
+    fn schedule(&self) -> Option<Vec<Duration>> {
+        Some(
+            self.rows
+                .iter()
+                .map(|index| self.trace.rows[*index].offset.div_f32(self.speed))
+                .collect(),
+        )
+    }
+}
+
+/// Replay sensors for every sensor id found in the trace columns.
+pub fn replay_sensors(path: &Path, speed: f32) -> DeviceResult<Vec<Box<dyn Sensor>>> {
+    if speed.is_nan() || speed <= 0.0 {
+        return Err(DeviceError::Config(format!(
+            "Replay speed has to be positive, got {}",
+            speed
+        )));
+    }
+    let trace = Arc::new(Trace::load(path)?);
+    println!(
+        "Replaying {} ({} samples, {:?} at x{})",
+        path.display(),
+        trace.rows.len(),
+        trace.duration(),
+        speed
+    );
+
+    let mut ids: Vec<String> = trace.columns().into_iter().map(|it| it.0).collect();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return Err(DeviceError::Trace(format!(
+            "{} has no measurement columns",
+            path.display()
+        )));
+    }
+
+    Ok(ids
+        .iter()
+        .map(|id| Box::new(ReplaySensor::new(id, trace.clone(), speed)) as Box<dyn Sensor>)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(file_name: &str, content: &str) -> DeviceResult<Trace> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(file_name);
+        fs::write(&path, content).unwrap();
+        Trace::load(&path)
+    }
+
+    fn trace(content: &str) -> Arc<Trace> {
+        Arc::new(load("trace.csv", content).unwrap())
+    }
+
+    fn temperatures(sensor: &mut ReplaySensor) -> Vec<f32> {
+        std::iter::from_fn(|| sensor.read().ok())
+            .map(|readings| readings[0].value)
+            .collect()
+    }
+
+    #[test]
+    fn reads_every_row_once_then_reports_the_end() {
+        let trace = trace("timestamp,aht20_temp,bmp280_pressure\n100,21,101.1\n110,22,\n130,23,101.3\n");
+        let mut aht20 = ReplaySensor::new("aht20", trace.clone(), 2.0);
+        let mut bmp280 = ReplaySensor::new("bmp280", trace, 2.0);
+
+        assert_eq!(
+            aht20.schedule(),
+            Some(vec![Duration::ZERO, Duration::from_secs(5), Duration::from_secs(15)])
+        );
+        for expected in [21.0, 22.0, 23.0] {
+            assert_eq!(aht20.read().unwrap(), vec![Reading::new(Quantity::Temperature, expected)]);
+        }
+        assert!(matches!(aht20.read(), Err(DeviceError::Trace(_))));
+
+        assert!(bmp280.read().is_ok());
+        //the empty cell replays a failed read
+        assert!(matches!(bmp280.read(), Err(DeviceError::Sensor { .. })));
+        assert!(bmp280.read().is_ok());
+        assert!(bmp280.read().is_err());
+    }
+
+    #[test]
+    fn sensors_only_read_their_own_rows() {
+        //what the recorder writes, the whole table after every sampled sensor
+        let trace = trace(concat!(
+            "timestamp,sensor,aht20_temp,bmp280_temp,demo_switch\n",
+            "100,aht20,21,,false\n",
+            "101,bmp280,21,25,false\n",
+            "105,aht20,22,25,false\n",
+            "111,bmp280,22,26,false\n",
+        ));
+        let mut aht20 = ReplaySensor::new("aht20", trace.clone(), 1.0);
+        let mut bmp280 = ReplaySensor::new("bmp280", trace, 1.0);
+
+        assert_eq!(aht20.schedule(), Some(vec![Duration::ZERO, Duration::from_secs(5)]));
+        assert_eq!(
+            bmp280.schedule(),
+            Some(vec![Duration::from_secs(1), Duration::from_secs(11)])
+        );
+        assert_eq!(temperatures(&mut aht20), [21.0, 22.0]);
+        assert_eq!(temperatures(&mut bmp280), [25.0, 26.0]);
+    }
+
+    #[test]
+    fn loads_jsonl_with_rfc3339_timestamps() {
+        let trace = load(
+            "trace.jsonl",
+            concat!(
+                "{\"timestamp\":\"2024-03-01T12:00:00Z\",\"sensor\":\"aht20\",\"aht20_temp\":21.5}\n",
+                "\n",
+                "{\"timestamp\":\"2024-03-01T12:00:02.5Z\",\"sensor\":\"aht20\",\"aht20_temp\":null}\n",
+                "{\"timestamp\":1709294410,\"sensor\":\"aht20\",\"aht20_temp\":22.5}\n",
+            ),
+        )
+        .unwrap();
+        assert_eq!(trace.duration(), Duration::from_secs(10));
+        assert_eq!(
+            trace.columns(),
+            [("aht20".to_string(), Quantity::Temperature, "aht20_temp".to_string())]
+        );
+
+        let mut aht20 = ReplaySensor::new("aht20", Arc::new(trace), 1.0);
+        assert_eq!(
+            aht20.schedule(),
+            Some(vec![Duration::ZERO, Duration::from_millis(2500), Duration::from_secs(10)])
+        );
+        assert_eq!(aht20.read().unwrap(), [Reading::new(Quantity::Temperature, 21.5)]);
+        //null is an unavailable value, a failed read
+        assert!(matches!(aht20.read(), Err(DeviceError::Sensor { .. })));
+        assert_eq!(aht20.read().unwrap(), [Reading::new(Quantity::Temperature, 22.5)]);
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        for timestamp in ["nan", "inf", "-inf", "yesterday"] {
+            let content = format!("timestamp,aht20_temp\n100,21\n{},22\n", timestamp);
+            let err = load("trace.csv", &content).err().unwrap();
+            assert!(matches!(err, DeviceError::Trace(_)), "{}", err);
+            assert!(err.to_string().contains("Line 3"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_timestamps_going_back() {
+        let err = load("trace.csv", "timestamp,aht20_temp\n100,21\n110,22\n105,23\n")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("sample 3 at 105"), "{}", err);
+
+        let err = load(
+            "trace.jsonl",
+            "{\"timestamp\":\"2024-03-01T12:00:10Z\"}\n{\"timestamp\":\"2024-03-01T12:00:00Z\"}\n",
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(err, DeviceError::Trace(_)), "{}", err);
+    }
+}
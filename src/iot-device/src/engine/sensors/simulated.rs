@@ -0,0 +1,210 @@
+use std::{
+    f32::consts::PI,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{Quantity, Reading, Sensor};
+use crate::error::{DeviceError, DeviceResult};
+
+const SECONDS_PER_DAY: f32 = 86_400.0;
+
+/// Shape of a single synthetic channel.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelProfile {
+    pub quantity: Quantity,
+    pub base: f32,
+    //amplitude of the daily sine, negative values invert the cycle (humidity peaks at night)
+    pub daily_amplitude: f32,
+    //max change of the random walk per read
+    pub walk_step: f32,
+    //random walk is pulled back into +-walk_limit
+    pub walk_limit: f32,
+    //size of a sudden step change (window opened, heater turned on)
+    pub step_size: f32,
+}
+
+struct Channel {
+    profile: ChannelProfile,
+    walk: f32,
+    step: f32,
+}
+
+/// Synthetic sensor that produces a daily sine cycle with random walk noise,
+/// occasional step changes and injected dropouts.
+pub struct SimulatedSensor {
+    id: String,
+    channels: Vec<Channel>,
+    rng: StdRng,
+    step_probability: f64,
+    dropout_probability: f64,
+    //reads left until the sensor recovers from a dropout
+    dropout_left: u32,
+}
+
+impl SimulatedSensor {
+    pub fn new(id: &str, profiles: &[ChannelProfile], seed: u64, dropout_probability: f64) -> SimulatedSensor {
+        let channels = profiles
+            .iter()
+            .map(|profile| Channel {
+                profile: *profile,
+                walk: 0.0,
+                step: 0.0,
+            })
+            .collect();
+
+        SimulatedSensor {
+            id: id.to_string(),
+            channels,
+            rng: StdRng::seed_from_u64(seed),
+            step_probability: 0.01,
+            dropout_probability,
+            dropout_left: 0,
+        }
+    }
+
+    //a read at the given fraction of the day, the rest only depends on the seed
+    fn sample(&mut self, phase: f32) -> DeviceResult<Vec<Reading>> {
+        if self.dropout_left == 0 && self.rng.gen_bool(self.dropout_probability) {
+            self.dropout_left = self.rng.gen_range(1..=5);
+        }
+        if self.dropout_left > 0 {
+            self.dropout_left -= 1;
+            return Err(DeviceError::sensor(&self.id, "simulated dropout"));
+        }
+
+        //minimum of the sine at 03:00, maximum at 15:00
+        let cycle = (2.0 * PI * (phase - 0.375)).sin();
+
+        let mut readings = Vec::with_capacity(self.channels.len());
+        for channel in self.channels.iter_mut() {
+            let profile = channel.profile;
+
+            if profile.walk_step > 0.0 {
+                channel.walk += self.rng.gen_range(-profile.walk_step..=profile.walk_step);
+                channel.walk = channel.walk.clamp(-profile.walk_limit, profile.walk_limit);
+            }
+            if profile.step_size != 0.0 && self.rng.gen_bool(self.step_probability) {
+                channel.step = if channel.step == 0.0 {
+                    profile.step_size * if self.rng.gen_bool(0.5) { 1.0 } else { -1.0 }
+                } else {
+                    0.0
+                };
+            }
+
+            let value = profile.base + profile.daily_amplitude * cycle + channel.walk + channel.step;
+            readings.push(Reading::new(profile.quantity, value));
+        }
+
+        Ok(readings)
+    }
+}
+
+//fraction of the current day, 0.0 at midnight UTC
+fn day_phase() -> f32 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs_f64())
+        .unwrap_or_default();
+    (seconds % SECONDS_PER_DAY as f64) as f32 / SECONDS_PER_DAY
+}
+
+impl Sensor for SimulatedSensor {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn quantities(&self) -> Vec<Quantity> {
+        self.channels.iter().map(|it| it.profile.quantity).collect()
+    }
+
+    fn read(&mut self) -> DeviceResult<Vec<Reading>> {
+        self.sample(day_phase())
+    }
+}
+
+/// Replacements for the board sensors, each one gets its own seed derived from `seed`.
+pub fn simulated_sensors(seed: u64, dropout_probability: f64) -> Vec<Box<dyn Sensor>> {
+    let temperature = ChannelProfile {
+        quantity: Quantity::Temperature,
+        base: 21.0,
+        daily_amplitude: 3.0,
+        walk_step: 0.1,
+        walk_limit: 1.0,
+        step_size: 2.0,
+    };
+    let humidity = ChannelProfile {
+        quantity: Quantity::Humidity,
+        base: 45.0,
+        daily_amplitude: -8.0,
+        walk_step: 0.5,
+        walk_limit: 4.0,
+        step_size: 10.0,
+    };
+    let pressure = ChannelProfile {
+        quantity: Quantity::Pressure,
+        base: 101.3,
+        daily_amplitude: 0.2,
+        walk_step: 0.02,
+        walk_limit: 0.8,
+        step_size: 0.0,
+    };
+
+    vec![
+        Box::new(SimulatedSensor::new("dht22", &[temperature, humidity], seed, dropout_probability)),
+        Box::new(SimulatedSensor::new(
+            "aht20",
+            &[temperature, humidity],
+            seed.wrapping_add(1),
+            dropout_probability,
+        )),
+        Box::new(SimulatedSensor::new(
+            "bmp280",
+            &[temperature, pressure],
+            seed.wrapping_add(2),
+            dropout_probability,
+        )),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //no walk so the values only move with the daily cycle and the step changes
+    const PROFILE: ChannelProfile = ChannelProfile {
+        quantity: Quantity::Temperature,
+        base: 20.0,
+        daily_amplitude: 3.0,
+        walk_step: 0.0,
+        walk_limit: 0.0,
+        step_size: 5.0,
+    };
+
+    fn run(seed: u64, reads: usize) -> Vec<Option<f32>> {
+        let mut sensor = SimulatedSensor::new("sim", &[PROFILE], seed, 0.1);
+        sensor.step_probability = 0.05;
+        (0..reads)
+            .map(|_| sensor.sample(0.375).ok().map(|readings| readings[0].value))
+            .collect()
+    }
+
+    #[test]
+    fn a_seed_gives_the_same_sequence() {
+        let first = run(42, 500);
+        assert_eq!(first, run(42, 500));
+        assert_ne!(first, run(43, 500));
+
+        //the run has to exercise dropouts and both step directions to prove anything
+        assert!(first.iter().any(|it| it.is_none()));
+        assert!(first.contains(&Some(25.0)) || first.contains(&Some(15.0)));
+        assert!(first.contains(&Some(20.0)));
+    }
+
+    #[test]
+    fn zero_dropout_rate_never_fails() {
+        let mut sensor = SimulatedSensor::new("sim", &[PROFILE], 7, 0.0);
+        assert!((0..1000).all(|_| sensor.sample(0.0).is_ok()));
+    }
+}
@@ -0,0 +1,74 @@
+use std::{fmt, io, time::Duration};
+
+/// Errors of the device runtime. Every variant carries enough context to tell
+/// which part of the board or which file failed.
+#[derive(Debug)]
+pub enum DeviceError {
+    //invalid configuration or command line, the message lists every problem
+    Config(String),
+    Io { context: String, source: io::Error },
+    Gpio { context: String, source: rppal::gpio::Error },
+    Spi { context: String, source: rppal::spi::Error },
+    I2c { context: String, source: rppal::i2c::Error },
+    Sensor { sensor: String, message: String },
+    Display(String),
+    Trace(String),
+    Mqtt { context: String, source: rumqttc::ClientError },
+    ChannelClosed(&'static str),
+    //a hardware request did not finish in time, the device may be hung
+    Timeout { device: String, after: Duration },
+    //the hardware worker could not take the request
+    Worker { device: String, message: String },
+}
+
+pub type DeviceResult<T> = Result<T, DeviceError>;
+
+impl DeviceError {
+    pub fn io(context: impl Into<String>, source: io::Error) -> DeviceError {
+        DeviceError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn sensor(sensor: &str, message: impl fmt::Display) -> DeviceError {
+        DeviceError::Sensor {
+            sensor: sensor.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::Config(message) => write!(f, "{}", message),
+            DeviceError::Io { context, source } => write!(f, "{}: {}", context, source),
+            DeviceError::Gpio { context, source } => write!(f, "GPIO {}: {}", context, source),
+            DeviceError::Spi { context, source } => write!(f, "SPI {}: {}", context, source),
+            DeviceError::I2c { context, source } => write!(f, "I2C {}: {}", context, source),
+            DeviceError::Sensor { sensor, message } => write!(f, "{}: {}", sensor, message),
+            DeviceError::Display(message) => write!(f, "Display: {}", message),
+            DeviceError::Trace(message) => write!(f, "Trace: {}", message),
+            DeviceError::Mqtt { context, source } => write!(f, "MQTT {}: {}", context, source),
+            DeviceError::ChannelClosed(name) => write!(f, "{} channel closed", name),
+            DeviceError::Timeout { device, after } => {
+                write!(f, "{}: no response within {}", device, humantime::format_duration(*after))
+            }
+            DeviceError::Worker { device, message } => write!(f, "{}: {}", device, message),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeviceError::Io { source, .. } => Some(source),
+            DeviceError::Gpio { source, .. } => Some(source),
+            DeviceError::Spi { source, .. } => Some(source),
+            DeviceError::I2c { source, .. } => Some(source),
+            DeviceError::Mqtt { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::{time::Duration, error::Error};
 
 use rumqttc::*;
 
-use crate::ProgramArgs;
+use crate::engine::config::DeviceConfig;
 
 /* async fn test_dht22() -> Result<(), Box<dyn Error>> {
     use dht_embedded::{Dht22, DhtSensor, NoopInterruptControl};
@@ -23,7 +23,10 @@ use crate::ProgramArgs;
 } */
 
 pub async fn test_i2c() -> Result<(), Box<dyn Error>> {
-    let i2c = rppal::i2c::I2c::new()?;
+    use crate::engine::sensors::{Bmp280Sensor, Sensor};
+
+    let mut bus = crate::engine::i2c_bus::I2cBusManager::new();
+    let i2c = bus.device(1, embedded_aht20::DEFAULT_I2C_ADDRESS, "aht20")?;
 
     /*let mut aht = aht20::Aht20::new(i2c, rppal::hal::Delay).unwrap();
     aht.read().unwrap();
@@ -36,13 +39,9 @@ pub async fn test_i2c() -> Result<(), Box<dyn Error>> {
     
     println!("Temperature: {:.2} °C, Relative humidity: {:.2} %", measure.temperature.celcius(), measure.relative_humidity);
 
-    let mut bmp280 = bmp280::Bmp280Builder::new()
-        .address(0x77) // Optional
-        .path("/dev/i2c-1") // Optional
-        .build().expect("Could not build device");
-
-        println!("temperature (bmp280): {:.2}C", bmp280.temperature_celsius()?);
-        println!("pressure_kpa (bmp280): {:.2}%", bmp280.pressure_kpa()?);
+    //same adapter, shared with the aht20 above
+    let mut bmp280 = Bmp280Sensor::new(bus.device(1, 0x77, "bmp280")?, 0x77)?;
+    println!("bmp280: {:?}", bmp280.read()?);
 
 
     Ok(())
@@ -87,6 +86,7 @@ pub fn ssd1680_test() {
     ssd1680.display_frame(&mut spi, &mut rppal::hal::Delay).unwrap();
 
 }
+#[allow(dead_code)]
 fn draw_text(display: &mut ssd1680::graphics::Display2in13, text: &str, x: i32, y: i32) {
     use embedded_graphics::prelude::*;
     use embedded_graphics::fonts::*;
@@ -103,8 +103,8 @@ fn draw_text(display: &mut ssd1680::graphics::Display2in13, text: &str, x: i32,
         .draw(display);
 }
 
-pub async fn mqtt_load(args: ProgramArgs) {
-    let mut mqttoptions = MqttOptions::new(args.id_device, "localhost", 1883);
+pub async fn mqtt_load(config: DeviceConfig) {
+    let mut mqttoptions = MqttOptions::new(config.device.id, "localhost", 1883);
 
     /* let ca: Vec<u8> = fs::read("ca_certificate.pem")
         .expect("Something went wrong reading certificate!");
@@ -119,7 +119,7 @@ pub async fn mqtt_load(args: ProgramArgs) {
         .set_keep_alive(Duration::from_secs(5))
         .set_pending_throttle(Duration::from_secs(2));
 
-    let (mut client, mut connection) = AsyncClient::new(mqttoptions, 10);
+    let (client, mut connection) = AsyncClient::new(mqttoptions, 10);
     client.publish("ServerRoute", QoS::AtLeastOnce, false, "My Text").await.unwrap();
     
     // Iterate to poll the eventloop for connection progress
@@ -1,27 +1,106 @@
 
-use std::thread;
-use std::time::Duration;
 
-use clap::{Parser, arg};
+use clap::Parser;
 
-use engine::{net_connector::NetConnectorSettings, ProgramArgs};
+use engine::{config::DeviceConfig, DeviceCommand, ProgramArgs};
 
-use crate::engine::ResultTable;
 
 pub mod engine;
+pub mod error;
 pub mod functests;
 pub mod proto;
 
 #[tokio::main]
 async fn main() {
     let args = ProgramArgs::parse();
-    let mut init_engine = engine::engine::Engine::new(args);
+    let config = match DeviceConfig::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(DeviceCommand::Scan { bus }) = args.command.as_ref() {
+        let buses = if bus.is_empty() { &config.sensors.i2c_buses } else { bus };
+        let all_opened = engine::i2c_scan::print_scan(buses);
+        //missing w1/IIO roots only mean the overlay is not loaded, they do not fail the scan
+        engine::sensors::iio::print_devices(&config.sensors.iio_root);
+        engine::sensors::ds18b20::print_probes(&config.sensors.ds18b20.w1_root);
+        std::process::exit(if all_opened { 0 } else { 1 });
+    }
+
+    let (sensors, display) = if args.simulate {
+        let seed = args.simulation_seed(&config.device.id);
+        let sensors = engine::sensors::simulated_sensors(seed, config.simulation.dropout_rate);
+        (sensors, headless_display(&config.display))
+    } else if let Some(path) = args.replay.as_ref() {
+        let sensors = match engine::sensors::replay_sensors(path, args.replay_speed) {
+            Ok(sensors) => sensors,
+            Err(err) => {
+                eprintln!("Could not start replay: {}", err);
+                std::process::exit(2);
+            }
+        };
+        (sensors, headless_display(&config.display))
+    } else {
+        let mut i2c = engine::i2c_bus::I2cBusManager::new();
+        let sensors = if config.sensors.autodetect {
+            engine::sensors::autodetected_sensors(&config.sensors, &mut i2c)
+        } else {
+            engine::sensors::hardware_sensors(&config.sensors, &mut i2c)
+        };
+        //the device keeps measuring and sending without a working display
+        let display = if config.display.enabled {
+            engine::display::open(&config.display, &mut i2c)
+                .map_err(|err| println!("Display disabled: {}", err))
+                .ok()
+        } else {
+            None
+        };
+        (sensors, display)
+    };
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(true);
+    });
+
+    let mut init_engine = engine::engine::Engine::new(config, sensors, display);
     init_engine.start_backgrund_tasks().await;
-    init_engine.run().await;
+    init_engine.run(shutdown_rx).await;
     //functests::ssd1680_test();
 
     
     
     //functests::embedded_aht20().await.unwrap();
     //functests::test_i2c().await;
-}
\ No newline at end of file
+}
+
+//simulation and replay have no panel, a headless display still renders the values
+fn headless_display(config: &engine::config::DisplayConfig) -> Option<Box<dyn engine::display::DisplayBackend>> {
+    if !(config.enabled && config.headless) {
+        return None;
+    }
+    engine::display::open(config, &mut engine::i2c_bus::I2cBusManager::new())
+        .map_err(|err| println!("Display disabled: {}", err))
+        .ok()
+}
+
+//SIGINT from the terminal or SIGTERM from systemd
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm.recv() => {}
+            }
+        }
+        Err(err) => {
+            println!("SIGTERM handler not installed: {}", err);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
@@ -1,18 +1,25 @@
 use std::{
     collections::HashMap,
-    time::{Duration, Instant},
+    path::PathBuf,
+    time::Duration,
 };
 
-use chrono::{serde, DurationRound, NaiveDateTime, SubsecRound};
-use clap::{Parser, Subcommand};
+use chrono::SubsecRound;
+use clap::{Args, Parser, Subcommand};
 use comfy_table::Table;
+use mqtt_tls::TlsSettings;
 use prost::Message;
 use reqwest::Url;
-use rumqttc::{AsyncClient, ConnAck, ConnectReturnCode, Event, Incoming, MqttOptions, Packet, QoS};
+use rumqttc::{
+    AsyncClient, ConnAck, ConnectReturnCode, ConnectionError, Event, Incoming, MqttOptions, Packet,
+    QoS, Transport,
+};
 use tokio::{self, task};
 
-use crate::proto::proto_broker_msgs::{self, ServerMessage};
+use crate::proto::proto_broker_msgs::{self, presence_message, PresenceMessage, ServerMessage};
 
+//the generated code has messages kditool never sends
+#[allow(dead_code)]
 mod proto;
 
 #[derive(Parser, Debug, Clone)]
@@ -43,11 +50,39 @@ enum Commands {
         username: Option<String>,
         #[arg(short, long)]
         password: Option<String>,
+
+        #[arg(long)]
+        port: Option<u16>,
+        #[command(flatten)]
+        tls: TlsArgs,
     },
     DisplayActivity {
         #[arg(long)]
         hostname: String,
     },
+    /// shows online/offline state of devices from the retained presence topic
+    Presence {
+        /// one device, all devices when omitted
+        #[arg(short, long)]
+        id_device: Option<String>,
+
+        #[arg(long)]
+        hostname: String,
+
+        #[arg(short, long)]
+        username: Option<String>,
+        #[arg(short, long)]
+        password: Option<String>,
+
+        #[arg(long)]
+        port: Option<u16>,
+        #[command(flatten)]
+        tls: TlsArgs,
+
+        /// keep printing presence changes instead of exiting after the retained states
+        #[arg(short, long)]
+        watch: bool,
+    },
     AverageMeasure {
         #[arg(short, long)]
         id_device: String,
@@ -60,6 +95,43 @@ enum Commands {
     },
 }
 
+#[derive(Args, Debug, Clone)]
+struct TlsArgs {
+    /// Connect over TLS (port defaults to 8883)
+    #[arg(long)]
+    tls: bool,
+    /// CA bundle (PEM) used to verify the broker, implies --tls; the system certificates when not given
+    #[arg(long)]
+    ca_file: Option<PathBuf>,
+    /// Client certificate (PEM) for mutual TLS
+    #[arg(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// Client private key (PEM) for mutual TLS
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// Name expected in the broker certificate when it differs from the hostname
+    #[arg(long)]
+    tls_server_name: Option<String>,
+    /// Accept any broker certificate, for testing only
+    #[arg(long)]
+    tls_insecure: bool,
+}
+
+impl TlsArgs {
+    fn settings(&self) -> Option<TlsSettings> {
+        if !self.tls && self.ca_file.is_none() {
+            return None;
+        }
+        Some(TlsSettings {
+            ca_file: self.ca_file.clone(),
+            client_cert_file: self.client_cert.clone(),
+            client_key_file: self.client_key.clone(),
+            server_name: self.tls_server_name.clone(),
+            insecure_skip_verify: self.tls_insecure,
+        })
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
@@ -70,10 +142,23 @@ async fn main() {
             duration,
             password,
             username,
-        } => iotdev(args, id_device, hostname, duration, password, username)
+            port,
+            tls,
+        } => iotdev(args, id_device, hostname, duration, username, password, port, tls)
             .await
             .unwrap(),
         Commands::DisplayActivity { hostname } => displayactitvity(hostname).await.unwrap(),
+        Commands::Presence {
+            id_device,
+            hostname,
+            username,
+            password,
+            port,
+            tls,
+            watch,
+        } => display_presence(id_device, hostname, username, password, port, tls, watch)
+            .await
+            .unwrap(),
         Commands::AverageMeasure { id_device, hostname, from_date, to_date } => display_average_measure(id_device, hostname, from_date, to_date).await.unwrap(),
     }
 }
@@ -104,6 +189,101 @@ async fn displayactitvity(hostname: String) -> Result<(), Box<dyn std::error::Er
     Ok(())
 }
 
+async fn display_presence(
+    id_device: Option<String>,
+    hostname: String,
+    username: Option<String>,
+    password: Option<String>,
+    port: Option<u16>,
+    tls: TlsArgs,
+    watch: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tls = tls.settings();
+    let port = port.unwrap_or(if tls.is_some() { 8883 } else { 1883 });
+    let mut mqttoptions = MqttOptions::new(format!("kditool-presence-{}", std::process::id()), hostname, port);
+    if let Some(tls) = tls.as_ref() {
+        mqttoptions.set_transport(Transport::Tls(tls.load()?));
+    }
+    mqttoptions.set_credentials(
+        username.unwrap_or("theserver".into()),
+        password.unwrap_or("myserverpass".into()),
+    );
+    mqttoptions.set_keep_alive(Duration::from_secs(5));
+
+    let (client, mut connection) = AsyncClient::new(mqttoptions, 10);
+    let topic = format!("iotserver/{}/presence", id_device.as_deref().unwrap_or("+"));
+    client.subscribe(topic, QoS::AtLeastOnce).await?;
+
+    //retained states arrive right after SubAck, collect them until the broker goes quiet
+    let mut states: HashMap<String, PresenceMessage> = HashMap::new();
+    let mut subscribed = false;
+    loop {
+        let notification = if subscribed && !watch {
+            match tokio::time::timeout(Duration::from_secs(2), connection.poll()).await {
+                Ok(notification) => notification,
+                Err(_) => break,
+            }
+        } else {
+            connection.poll().await
+        };
+
+        match notification {
+            Err(ConnectionError::Tls(err)) => return Err(format!("TLS connection failed: {}", err).into()),
+            Err(err) => return Err(err.into()),
+            Ok(Event::Incoming(Packet::SubAck(_))) => subscribed = true,
+            Ok(Event::Incoming(Incoming::Publish(packet))) => {
+                let Ok(message) = PresenceMessage::decode(packet.payload.clone()) else {
+                    println!("Invalid presence message on {}", packet.topic);
+                    continue;
+                };
+                if watch {
+                    println!(
+                        "{} {}: {}",
+                        presence_since(&message),
+                        message.id_device,
+                        presence_state(&message)
+                    );
+                }
+                states.insert(message.id_device.clone(), message);
+            }
+            _ => (),
+        }
+    }
+
+    let mut sorted: Vec<&PresenceMessage> = states.values().collect();
+    sorted.sort_by(|a, b| a.id_device.cmp(&b.id_device));
+
+    let mut table = Table::new();
+    table.set_header(vec!["Device Name", "Presence", "Since"]);
+    for message in sorted {
+        table.add_row(vec![
+            message.id_device.clone(),
+            presence_state(message).to_string(),
+            presence_since(message),
+        ]);
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+fn presence_state(message: &PresenceMessage) -> &'static str {
+    match message.state() {
+        presence_message::State::Online => "online",
+        presence_message::State::Offline => "offline",
+        presence_message::State::ConnectionLost => "connection lost",
+    }
+}
+
+fn presence_since(message: &PresenceMessage) -> String {
+    message
+        .timestamp
+        .as_ref()
+        .and_then(|it| chrono::DateTime::from_timestamp(it.seconds, it.nanos.max(0) as u32))
+        .map(|it| it.to_rfc3339())
+        .unwrap_or_else(|| "-".to_string())
+}
+
 async fn display_average_measure(id_device: String, hostname: String, from_date: String, to_date: Option<String>) -> Result<(), Box<dyn std::error::Error>>{
     //parse user input as weak
     let from_date = humantime::parse_rfc3339_weak(&from_date).expect("wrong from_date");
@@ -127,6 +307,7 @@ async fn display_average_measure(id_device: String, hostname: String, from_date:
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn iotdev(
     args: Cli,
     id_device: String,
@@ -134,10 +315,17 @@ async fn iotdev(
     waiting_duration: u64,
     username: Option<String>,
     password: Option<String>,
+    port: Option<u16>,
+    tls: TlsArgs,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("Starting thread, args: {:?}", args);
 
-    let mut mqttoptions = MqttOptions::new(id_device.clone(), hostname.clone(), 1883);
+    let tls = tls.settings();
+    let port = port.unwrap_or(if tls.is_some() { 8883 } else { 1883 });
+    let mut mqttoptions = MqttOptions::new(id_device.clone(), hostname.clone(), port);
+    if let Some(tls) = tls.as_ref() {
+        mqttoptions.set_transport(Transport::Tls(tls.load()?));
+    }
     mqttoptions.set_credentials(
         username.unwrap_or("theserver".into()),
         password.unwrap_or("myserverpass".into()),
@@ -148,16 +336,21 @@ async fn iotdev(
         .set_pending_throttle(Duration::from_secs(2));
 
     let (client, mut connection) = AsyncClient::new(mqttoptions, 0);
-    let (ts, mut receiver) = tokio::sync::mpsc::channel::<ServerMessage>(5);
+    let (ts, _receiver) = tokio::sync::mpsc::channel::<ServerMessage>(5);
 
     let (thread_client, thread_iddevice) = (client.clone(), id_device.clone());
-    let _ = tokio::spawn(async move {
+    tokio::spawn(async move {
         let (client, id_device) = (thread_client, thread_iddevice);
-        let sender = ts;
+        let _sender = ts;
         loop {
             let notification = connection.poll().await;
             println!("Notification: {:?}", notification);
             match notification {
+                Err(ConnectionError::Tls(err)) => {
+                    println!("TLS connection failed: {}", err);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
                 Err(_) => {
                     tokio::time::sleep(Duration::from_secs(5)).await;
                     continue;
@@ -0,0 +1,263 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rumqttc::{
+    tokio_rustls::rustls::{
+        self,
+        client::{
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+            WebPkiServerVerifier,
+        },
+        crypto::{self, WebPkiSupportedAlgorithms},
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+        ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    },
+    TlsConfiguration,
+};
+
+/// Why the TLS configuration could not be built, every variant names the file it comes from.
+#[derive(Debug)]
+pub enum TlsError {
+    Io { context: String, source: io::Error },
+    Invalid(String),
+}
+
+pub type TlsResult<T> = Result<T, TlsError>;
+
+impl TlsError {
+    fn io(context: String, source: io::Error) -> TlsError {
+        TlsError::Io { context, source }
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io { context, source } => write!(f, "TLS: could not {}: {}", context, source),
+            TlsError::Invalid(message) => write!(f, "TLS: {}", message),
+        }
+    }
+}
+
+impl Error for TlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TlsError::Io { source, .. } => Some(source),
+            TlsError::Invalid(_) => None,
+        }
+    }
+}
+
+/// Certificates and verification options of the MQTT TLS connection.
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    //CA bundle verifying the broker, the system certificate store when None
+    pub ca_file: Option<PathBuf>,
+    //client certificate and key for mutual TLS
+    pub client_cert_file: Option<PathBuf>,
+    pub client_key_file: Option<PathBuf>,
+    //name checked against the broker certificate instead of the host, SNI still carries the host
+    pub server_name: Option<String>,
+    //accept any broker certificate, for testing only
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsSettings {
+    /// Loads the certificate files and builds the rustls configuration, every error names the file it comes from.
+    pub fn load(&self) -> TlsResult<TlsConfiguration> {
+        let algorithms = crypto::ring::default_provider().signature_verification_algorithms;
+        let builder = if self.insecure_skip_verify {
+            //no certificate is checked, so no CA is read either
+            let verifier = Arc::new(CustomServerVerifier {
+                inner: None,
+                server_name: None,
+                algorithms,
+            });
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(verifier)
+        } else if let Some(name) = self.server_name.as_ref() {
+            let inner = WebPkiServerVerifier::builder(Arc::new(self.roots()?))
+                .build()
+                .map_err(|err| TlsError::Invalid(format!("could not build certificate verifier: {}", err)))?;
+            let server_name = ServerName::try_from(name.as_str())
+                .map_err(|err| TlsError::Invalid(format!("invalid server name '{}': {}", name, err)))?
+                .to_owned();
+            let verifier = Arc::new(CustomServerVerifier {
+                inner: Some(inner),
+                server_name: Some(server_name),
+                algorithms,
+            });
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(verifier)
+        } else {
+            ClientConfig::builder().with_root_certificates(self.roots()?)
+        };
+
+        let config = match (self.client_cert_file.as_ref(), self.client_key_file.as_ref()) {
+            (Some(cert_file), Some(key_file)) => {
+                let certs = read_certs(cert_file, "client")?;
+                let key = read_key(key_file)?;
+                builder.with_client_auth_cert(certs, key).map_err(|err| {
+                    TlsError::Invalid(format!(
+                        "client certificate {} does not match key {}: {}",
+                        cert_file.display(),
+                        key_file.display(),
+                        err
+                    ))
+                })?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(TlsError::Invalid(
+                    "client certificate and client key have to be given together".to_string(),
+                ))
+            }
+        };
+
+        Ok(TlsConfiguration::Rustls(Arc::new(config)))
+    }
+
+    fn roots(&self) -> TlsResult<RootCertStore> {
+        let (certs, source) = match self.ca_file.as_ref() {
+            Some(path) => (read_certs(path, "CA")?, path.display().to_string()),
+            None => {
+                let certs = rustls_native_certs::load_native_certs()
+                    .map_err(|err| TlsError::io("load the system CA certificates".to_string(), err))?;
+                (certs, "the system certificate store".to_string())
+            }
+        };
+        let mut roots = RootCertStore::empty();
+        let (added, _ignored) = roots.add_parsable_certificates(certs);
+        if added == 0 {
+            return Err(TlsError::Invalid(format!("no valid CA certificate in {}", source)));
+        }
+        Ok(roots)
+    }
+}
+
+fn read_certs(path: &Path, kind: &str) -> TlsResult<Vec<CertificateDer<'static>>> {
+    let content = fs::read(path)
+        .map_err(|err| TlsError::io(format!("read {} certificate {}", kind, path.display()), err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(content.as_slice()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| TlsError::Invalid(format!("invalid PEM in {}: {}", path.display(), err)))?;
+    if certs.is_empty() {
+        return Err(TlsError::Invalid(format!("no certificate found in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> TlsResult<PrivateKeyDer<'static>> {
+    let content = fs::read(path)
+        .map_err(|err| TlsError::io(format!("read client key {}", path.display()), err))?;
+    rustls_pemfile::private_key(&mut BufReader::new(content.as_slice()))
+        .map_err(|err| TlsError::Invalid(format!("invalid PEM in {}: {}", path.display(), err)))?
+        .ok_or_else(|| TlsError::Invalid(format!("no private key found in {}", path.display())))
+}
+
+//webpki verification against a configured name, or no certificate verification at all
+#[derive(Debug)]
+struct CustomServerVerifier {
+    //None accepts any certificate
+    inner: Option<Arc<WebPkiServerVerifier>>,
+    server_name: Option<ServerName<'static>>,
+    //the handshake signatures are checked either way
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for CustomServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let Some(inner) = self.inner.as_ref() else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        let server_name = self.server_name.as_ref().unwrap_or(server_name);
+        inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_missing_ca_file() {
+        let settings = TlsSettings {
+            ca_file: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..TlsSettings::default()
+        };
+        let err = settings.load().unwrap_err();
+        assert!(matches!(err, TlsError::Io { .. }));
+        assert!(err.to_string().contains("/nonexistent/ca.pem"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_a_file_without_certificates() {
+        let dir = std::env::temp_dir().join(format!("mqtt-tls-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let ca_file = dir.join("empty.pem");
+        fs::write(&ca_file, "not a certificate\n").unwrap();
+
+        let settings = TlsSettings {
+            ca_file: Some(ca_file),
+            ..TlsSettings::default()
+        };
+        let err = settings.load().unwrap_err();
+        let _ = fs::remove_dir_all(&dir);
+        assert!(matches!(err, TlsError::Invalid(_)));
+        assert!(err.to_string().contains("no certificate found"), "{}", err);
+    }
+
+    #[test]
+    fn insecure_needs_no_ca_file() {
+        let settings = TlsSettings {
+            ca_file: None,
+            insecure_skip_verify: true,
+            ..TlsSettings::default()
+        };
+        assert!(settings.load().is_ok());
+
+        //not even a broken one, it is never read
+        let settings = TlsSettings {
+            ca_file: Some(PathBuf::from("/nonexistent/ca.pem")),
+            insecure_skip_verify: true,
+            ..TlsSettings::default()
+        };
+        assert!(settings.load().is_ok());
+    }
+}